@@ -1,49 +1,365 @@
 use std::collections::HashMap;
 
-const HELP_MSG: &str = 
-"Bedrock Linux \x1b[1mP\x1b[0mackage \x1b[1mM\x1b[0manager \x1b[1mM\x1b[0manager
+use colored::Colorize;
 
-Usage: pmm [options] [command] [args]
+pub struct Flag {
+	pub long:  &'static str,
+	pub short: Option<char>,
+	pub value: Option<&'static str>, // what it takes, `--backends=a,b`
+	pub help:  &'static str,
+}
+
+const fn flag(long: &'static str, short: Option<char>, value: Option<&'static str>, help: &'static str) -> Flag {
+	Flag { long, short, value, help }
+}
+
+pub struct Command {
+	pub name:   &'static str,
+	pub usage:  &'static str,
+	pub help:   &'static str,
+	pub min:    usize,
+	pub max:    Option<usize>,
+	pub flags:  &'static [Flag],
+	pub subs:   &'static [Command],
+	pub runs:   bool, // does something without one of its `subs`
+	pub hidden: bool,
+}
+
+const fn cmd(name: &'static str, usage: &'static str, help: &'static str) -> Command {
+	Command { name, usage, help, min: 0, max: Some(0), flags: &[], subs: &[], runs: true, hidden: false }
+}
+
+impl Command {
+	const fn args(self, min: usize, max: Option<usize>) -> Self {
+		Self { min, max, ..self }
+	}
+
+	const fn flags(self, flags: &'static [Flag]) -> Self {
+		Self { flags, ..self }
+	}
+
+	const fn subs(self, subs: &'static [Command], runs: bool) -> Self {
+		Self { subs, runs, ..self }
+	}
+
+	const fn hidden(self) -> Self {
+		Self { hidden: true, ..self }
+	}
+
+	fn flag(&self, long: &str) -> Option<&'static Flag> {
+		self.flags.iter().find(|f| f.long == long)
+	}
+
+	pub fn help(&self, path: &str) -> String {
+		let mut s = format!("{}\n\n{}\n", format!("Usage: pmm {path} {}", self.usage).trim_end(), self.help);
+
+		if !self.subs.is_empty() {
+			s.push_str(&format!("\n{}\n{}", "COMMANDS".bold(), table(self.subs.iter().filter(|c| !c.hidden)
+				.map(|c| (format!("{} {}", c.name, c.usage), c.help)))));
+		}
+		if !self.flags.is_empty() {
+			s.push_str(&format!("\n{}\n{}", "OPTIONS".bold(), table(self.flags.iter().map(Flag::row))));
+		}
+
+		s
+	}
+}
+
+impl Flag {
+	fn row(&self) -> (String, &'static str) {
+		let long = match self.value {
+			Some(v) => format!("--{}={v}", self.long),
+			None    => format!("--{}", self.long),
+		};
+
+		(self.short.map_or(long.clone(), |s| format!("-{s}, {long}")), self.help)
+	}
+}
+
+pub const FLAGS: &[Flag] = &[
+	flag("help",     Some('h'), None,        "Show this help message, or the command's"),
+	flag("version",  Some('v'), None,        "Show version information"),
+	flag("backends", None,      Some("a,b"), "Only use the given backends"),
+	flag("sorted",   None,      None,        "Wait for all backends and print results by priority"),
+	flag("verbose",  None,      None,        "Show backend error data and stack traces"),
+	flag("refresh",  None,      None,        "Don't use cached responses"),
+	flag("offline",  None,      None,        "Only use cached responses, implied if the network is unreachable"),
+	flag("dry",      None,      None,        "Only update the world file, never run anything as root"),
+	flag("ui",       None,      Some("f"),   "Take another package manager's commands, `pacman`, `apt` or `xbps`"),
+	flag("config",   None,      Some("f"),   "Use this config, unless `$PMM_CONFIG` is set"),
+];
+
+pub const COMMANDS: &[Command] = &[
+	cmd("search", "[terms...]", "Search every backend for packages").args(0, None),
+	cmd("info", "<[backend/]pkg>", "Show what the backends know about a package").args(1, Some(1)),
+	cmd("add", "<[backend/]pkg>", "Install a package, asking which backend to use if several have it").args(1, Some(1)),
+	cmd("del", "<pkg>...", "Remove packages installed through pmm").args(1, None),
+	cmd("backends", "", "List the loaded backends").subs(&[
+		cmd("check", "", "Check the health of every backend"),
+	], true),
+	cmd("cache", "<command>", "Manage cached responses").subs(&[
+		cmd("clean", "", "Remove every cached response"),
+	], false),
+	cmd("init", "", "Write a config for the backends whose package managers are installed").flags(&[
+		flag("backend-dir",  None, Some("d"), "Where to look for backends, ./backends by default"),
+		flag("bedrock-root", None, Some("d"), "Where the strata are, /bedrock by default"),
+	]),
+	cmd("config", "<command>", "Inspect or edit the config").subs(&[
+		cmd("show", "", "Print the config with its includes merged in, paths resolved and `:env` filled in"),
+		cmd("get", "<key>", "Print a key's value, as the config ends up with it").args(1, Some(1)),
+		cmd("set", "<key> <value>", "Set a key in the config file, bare words are strings").args(2, Some(2)),
+		cmd("unset", "<key>", "Remove a key from the config file").args(1, Some(1)),
+	], false),
+	cmd("completions", "<shell>", "Print a completion script for bash, zsh or fish").args(1, Some(1)),
+	cmd("__complete", "[words...]", "What the last word could be, for the completion scripts").args(0, None).hidden(),
+	cmd("test", "", "Call every backend's `test`").hidden(),
+];
+
+const FLAVOURS: &str = "\
+	\tpacman  -Ss search, -Si info, -S add, -R del, -Sc clean the cache
+	apt     search, show, install, remove, clean
+	xbps    xbps-query -Rs search, xbps-query -R info, xbps-install add, xbps-remove del,
+	        xbps-remove -O clean the cache
+	`backends` and `cache clean` stay as they are in all of them.
+	Set with `:ui` in the config or --ui. Upgrading isn't supported in any of them yet";
+
+const EXIT_CODES: &str = "\
+	\t1  pmm error
+	2  not found
+	3  network error
+	4  backend timed out
+	5  backend bug
+	6  other backend error
+If several backends fail, the highest code is used";
+
+// two aligned columns
+fn table(rows: impl Iterator<Item = (String, &'static str)>) -> String {
+	let rows = rows.collect::<Vec<_>>();
+	let width = rows.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+
+	rows.iter().map(|(l, r)| format!("\t{l:<width$}  {r}\n")).collect()
+}
+
+fn help() -> String {
+	format!("Bedrock Linux {}ackage {}anager {}anager\n\nUsage: pmm [options] <command> [args]\n\n{}\n{}\n{}\n{}\n{}\n{FLAVOURS}\n\n{}\n{EXIT_CODES}\n\nRun `pmm <command> --help` for more on a command",
+		"P".bold(), "M".bold(), "M".bold(),
+		"COMMANDS".bold(), table(COMMANDS.iter().filter(|c| !c.hidden).map(|c| (format!("{} {}", c.name, c.usage), c.help))),
+		"OPTIONS".bold(), table(FLAGS.iter().map(Flag::row)),
+		"UI FLAVOURS".bold(), "EXIT CODES".bold())
+}
+
+fn unknown(what: &str, name: &str, among: &[Command]) -> ! {
+	match crate::util::closest(name, among.iter().filter(|c| !c.hidden).map(|c| c.name)) {
+		Some(c) => crate::err!("Unknown {what} `{name}`, did you mean `{c}`?"),
+		None    => crate::err!("Unknown {what} `{name}`\nRun with {} for usage information", "--help".bold()),
+	}
+}
+
+enum Token {
+	Opt(String),  // `-x` or `--x`, global flags are taken out straight away
+	Word(String), // everything after `--` is one, whatever it looks like
+}
 
-\x1b[1mOPTIONS\x1b[0m
-	-h, --help       Show this help message
-	-v, --version    Show version information";
+impl Token {
+	fn into_string(self) -> String {
+		match self {
+			Self::Opt(s) | Self::Word(s) => s,
+		}
+	}
+}
 
-#[derive(Default, Debug)]
-pub struct Args(HashMap<&'static str, Option<&'static str>>);
+// global flags can go anywhere, the rest is left for the command (or the UI flavour) to make sense of
+#[derive(Default)]
+pub struct Args {
+	flags: HashMap<&'static str, Option<String>>,
+	rest:  Vec<Token>,
+}
 
 impl Args {
 	pub fn get(&self, key: &str) -> bool {
-		self.0.get(key).is_some_and(|v| v.is_none())
+		self.flags.contains_key(key)
 	}
 
-	pub fn get_with_opt(&self, key: &str) -> Option<&'static str> {
-		self.0.get(key).map(|v| v.unwrap_or_else(||
-			crate::err!("Missing value for arg '{key}'\nRun with {} for usage information", "--help".bold())))
+	pub fn get_with_opt(&self, key: &str) -> Option<&str> {
+		self.flags.get(key).and_then(|v| v.as_deref())
 	}
 
-	pub fn parse<I: std::iter::Iterator<Item = String>>(args: I) -> (Self, Vec<String>) {
-		args.fold((Args::default(), Vec::new()), |mut acc, arg| {
-			match arg.starts_with('-') {
-				true => {
-					let arg = Box::leak(arg.into_boxed_str()).trim_start_matches("-");
-					let (key, val) = arg.split_once("=").map_or((arg, None), |(k, v)| (k, Some(v)));
-					acc.0.0.insert(key, val);
+	// sets `flag` from `--long=value`, or the next argument if it takes one
+	fn set(&mut self, flag: &'static Flag, value: Option<String>, next: &mut impl Iterator<Item = String>) {
+		let value = match (flag.value, value) {
+			(Some(_), Some(v)) => Some(v),
+			(Some(_), None)    => Some(next.next()
+				.unwrap_or_else(|| crate::err!("`--{}` expected a value", flag.long))),
+			(None, Some(_))    => crate::err!("`--{}` doesn't take a value", flag.long),
+			(None, None)       => None,
+		};
+
+		self.flags.insert(flag.long, value);
+	}
+
+	pub fn parse<I: std::iter::Iterator<Item = String>>(args: I) -> Self {
+		let mut args = args.into_iter();
+		let mut acc = Self::default();
+
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--" => acc.rest.extend(args.by_ref().map(Token::Word)),
+				// global short flags can be bunched up with each other, `-hv`. anything else in the bunch
+				// makes it the command's or the UI flavour's whole, `-Sv` is pacman's
+				a if a.len() > 1 && a.starts_with('-') && !a.starts_with("--") => {
+					let flags = a[1..].chars()
+						.map(|c| FLAGS.iter().find(|f| f.short == Some(c)))
+						.collect::<Option<Vec<_>>>();
+
+					match flags {
+						Some(flags) => flags.into_iter().for_each(|f| acc.set(f, None, &mut args)),
+						None => acc.rest.push(Token::Opt(arg)),
+					}
 				},
-				_ => acc.1.push(arg),
-			}; acc
-		})
+				a => match a.strip_prefix("--").map(|a| a.split_once('=').map_or((a, None), |(k, v)| (k, Some(v.to_string())))) {
+					Some((k, v)) if FLAGS.iter().any(|f| f.long == k) =>
+						acc.set(FLAGS.iter().find(|f| f.long == k).unwrap(), v, &mut args),
+					_ if a.len() > 1 && a.starts_with('-') => acc.rest.push(Token::Opt(arg)),
+					_ => acc.rest.push(Token::Word(arg)),
+				},
+			}
+		}
+
+		acc
 	}
 
 	pub fn handle_base_flags(&self) {
-		if self.get("h") || self.get("help") {
-			println!("{HELP_MSG}");
+		if self.get("help") {
+			// `pmm backends check --help`
+			let (mut path, mut among, mut cmd) = (Vec::new(), COMMANDS, None);
+			for w in self.rest.iter().filter_map(|t| match t {
+				Token::Word(w) => Some(w.as_str()),
+				Token::Opt(_)  => None,
+			}) {
+				let Some(c) = among.iter().find(|c| c.name == w) else { break };
+				(among, cmd) = (c.subs, Some(c));
+				path.push(c.name);
+			}
+
+			println!("{}", cmd.map_or_else(help, |c| c.help(&path.join(" "))));
 			std::process::exit(0);
 		}
 
-		if self.get("v") || self.get("version") {
+		if self.get("version") {
 			println!("{}", env!("CARGO_PKG_VERSION"));
 			std::process::exit(0);
 		}
 	}
+
+	// whether the first word is `name`, before it's known which grammar applies
+	pub fn is_command(&self, name: &str) -> bool {
+		self.rest.iter().find_map(|t| match t {
+			Token::Word(w) => Some(w == name),
+			Token::Opt(_)  => None,
+		}).unwrap_or(false)
+	}
+
+	// one of `cmd`'s own flags, `--long[=value]` or `-ab`
+	fn option(&mut self, cmd: &'static Command, name: &str, o: &str, next: &mut impl Iterator<Item = String>) {
+		match o.strip_prefix("--") {
+			Some(l) => {
+				let (k, v) = l.split_once('=').map_or((l, None), |(k, v)| (k, Some(v.to_string())));
+				let f = cmd.flag(k).unwrap_or_else(||
+					crate::err!("Unknown flag `--{k}` for `{name}`\nRun `pmm {name} --help` for usage information"));
+				self.set(f, v, next);
+			},
+			None => o[1..].chars().for_each(|c| {
+				let f = cmd.flags.iter().find(|f| f.short == Some(c)).unwrap_or_else(||
+					crate::err!("Unknown flag `-{c}` for `{name}`\nRun `pmm {name} --help` for usage information"));
+				self.set(f, None, next);
+			}),
+		}
+	}
+
+	// pmm's own grammar, `backends check` is `["backends", "check"]` along with the command's arguments.
+	// the command's flags come after it, and end up with the global ones
+	pub fn command(&mut self) -> (Vec<&'static str>, Vec<String>) {
+		let mut rest = std::mem::take(&mut self.rest).into_iter();
+		let (mut path, mut words) = (Vec::new(), Vec::new());
+		let (mut among, mut cmd): (&[Command], Option<&'static Command>) = (COMMANDS, None);
+
+		while let Some(t) = rest.next() {
+			match (t, cmd) {
+				(Token::Opt(o), None) => crate::err!("Unknown flag `{o}`\nRun with {} for usage information", "--help".bold()),
+				(Token::Opt(o), Some(c)) => self.option(c, &path.join(" "), &o, &mut rest.by_ref().map(Token::into_string)),
+
+				(Token::Word(w), _) if !words.is_empty() || among.is_empty() => words.push(w),
+				(Token::Word(w), _) => match (among.iter().find(|c| c.name == w), cmd) {
+					(Some(c), _) => {
+						(among, cmd) = (c.subs, Some(c));
+						path.push(c.name);
+					},
+					(None, None) => unknown("action", &w, COMMANDS),
+					(None, Some(c)) if !c.runs || c.max == Some(0) => unknown(&format!("`{}` action", c.name), &w, c.subs),
+					(None, Some(_)) => words.push(w),
+				},
+			}
+		}
+
+		let Some(cmd) = cmd else {
+			crate::err!("No action given\nRun with {} for usage information", "--help".bold())
+		};
+		let name = path.join(" ");
+
+		if !cmd.runs {
+			crate::err!("`{name}` expected an action, one of {}", cmd.subs.iter()
+				.map(|c| format!("`{}`", c.name)).collect::<Vec<_>>().join(", "));
+		}
+
+		match (words.len(), cmd.min, cmd.max) {
+			(n, min, _) if n < min => crate::err!("`{name}` expected {}", match min {
+				1 => String::from("an argument"),
+				m => format!("at least {m} arguments"),
+			}),
+			(n, _, Some(max)) if n > max => crate::err!("`{name}` expected {}", match max {
+				0 => String::from("no arguments"),
+				1 => String::from("only one argument"),
+				m => format!("at most {m} arguments"),
+			}),
+			_ => {},
+		}
+
+		(path, words)
+	}
+
+	// for the UI flavours, which have flags of their own: short flags as they came (`Syu` for `-Syu`)
+	// and everything else
+	pub fn loose(&mut self) -> (Vec<String>, Vec<String>) {
+		std::mem::take(&mut self.rest).into_iter().fold((Vec::new(), Vec::new()), |(mut short, mut words), t| {
+			match t {
+				Token::Opt(o) if o.starts_with("--") => crate::err!("Unknown flag `{o}`"),
+				Token::Opt(o)  => short.push(o[1..].to_string()),
+				Token::Word(w) => words.push(w),
+			}; (short, words)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Args;
+
+	fn parse(args: &[&str]) -> Args {
+		Args::parse(args.iter().map(|a| a.to_string()))
+	}
+
+	#[test]
+	fn short_flags() {
+		let args = parse(&["-hv"]);
+		assert!(args.get("help") && args.get("version"));
+
+		let mut args = parse(&["search", "-hv", "foo"]);
+		assert!(args.get("help") && args.get("version"));
+		assert_eq!(args.command(), (vec!["search"], vec![String::from("foo")]));
+
+		// bunched with anything else it's the UI flavour's
+		let mut args = parse(&["-Sv", "foo"]);
+		assert!(!args.get("help") && !args.get("version"));
+		assert_eq!(args.loose(), (vec![String::from("Sv")], vec![String::from("foo")]));
+	}
 }
@@ -1,66 +1,196 @@
 use std::fmt::{self, Display, Formatter};
-use janetrs::{Janet, JanetArray, JanetKeyword, JanetStruct};
+use std::path::{Path, PathBuf};
+use janetrs::{Janet, JanetKeyword, JanetStruct, TaggedJanet};
 
 use colored::Colorize;
+use crate::config::Instance;
 use crate::util::JanetInto;
 use crate::keyword;
 
+// functions a backend can export, in the order `pmm backends` lists them
+pub const CAPABILITIES: &[&str] = &["search", "info", "add", "del", "health"];
+
 pub struct Backend {
-	pub name:   String,
-	pub colour: (u8, u8, u8),
+	pub name:    String,
+	pub colour:  (u8, u8, u8),
+	pub path:    PathBuf,
+	pub caps:    Vec<&'static str>,
+	pub stratum: Option<String>,
+}
+
+impl Backend {
+	pub fn exports(&self, func: &str) -> bool {
+		self.caps.contains(&func)
+	}
+
+	// `debian:apt` if it's bound to a stratum, discovered ones are named like that already
+	pub fn qualified(&self) -> String {
+		match &self.stratum {
+			Some(s) if !self.name.starts_with(&format!("{s}:")) => format!("{s}:{}", self.name),
+			_ => self.name.clone(),
+		}
+	}
 }
 
 impl Display for Backend {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 		write!(f, "\x1b[38;2;{};{};{}m{}\x1b[0m", 
 			self.colour.0, self.colour.1, self.colour.2, 
-			self.name.bold())
+			self.qualified().bold())
 	}
 }
 
 pub struct Backends(Vec<Backend>);
 
+fn stem(file: &Path) -> String {
+	file.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+// `.janet` files in `dir`
+fn files(dir: &str) -> Vec<PathBuf> {
+	let mut files = std::fs::read_dir(dir)
+		.unwrap_or_else(|e| crate::err!("{dir}: {e}"))
+		.filter_map(|entry| {
+			let path = entry.unwrap_or_else(|e| crate::err!("{e}")).path();
+			// skip editor swap files and the like
+			(!path.is_dir() 
+				&& path.extension().is_some_and(|e| e == "janet")
+				&& !path.file_name().unwrap().to_string_lossy().starts_with('.'))
+				.then_some(path)
+		})
+		.collect::<Vec<_>>();
+
+	files.sort_unstable();
+	files
+}
+
+// `(def DETECT ["apt-get" "dpkg"])`, the binaries that mean a package manager is there.
+// just the file's name by default. read from the source, the file itself only runs once it's loaded
+fn detect(rt: &mut janetrs::client::JanetClient, file: &Path) -> Vec<String> {
+	let v = rt.run(format!("(pmm/detect {:?})", file.to_string_lossy()))
+		.unwrap_or_else(|e| crate::err!("{}: {e}", file.display()));
+
+	match v.unwrap() {
+		TaggedJanet::Nil       => vec![stem(file)],
+		TaggedJanet::String(s) => vec![s.to_string()],
+		TaggedJanet::Tuple(t)  => t.iter().map(ToString::to_string).collect(),
+		_ => unreachable!("`pmm/detect` gives nil, a string or a tuple"),
+	}
+}
+
 impl Backends {
-	pub fn from_dir(rt: &mut janetrs::client::JanetClient, path: impl AsRef<std::path::Path>) -> Self {
-		let ns = std::fs::read_dir(&path)
-			.unwrap_or_else(|e| crate::err!("{}: {e}", path.as_ref().display()))
-			.filter_map(|entry| {
-				let path = entry.unwrap_or_else(|e| crate::err!("{e}")).path();
-				(!path.is_dir()).then_some(path)
-			})
-			.map(|path| {
-				let mut path = std::path::absolute(&path).unwrap();
-				path.set_extension("");
-				let ns = path.file_stem().unwrap().to_string_lossy();
-
-				rt.run(format!("(import @{} :as {ns})", path.to_str().unwrap()))
-					.unwrap_or_else(|e| crate::err!("{e}"));
-
-				let env = rt.env().unwrap();
-
-				Backend {
-					name:   ns.to_string(),
-					colour: match env.resolve(format!("{ns}/COLOUR")) {
-						None => {
-							crate::warn!("{ns}: `COLOUR` not specified, set to `nil` to use the default");
-							(u8::MAX, u8::MAX, u8::MAX)
-						},
-						Some(c) => c.janet_into(),
-					},
-				}
-			})
+	pub fn from_dir(rt: &mut janetrs::client::JanetClient, config: &crate::config::Config) -> Self {
+		let path = &config.backend_dir;
+		let found = match config.discover {
+			true  => Self::discover(rt, path),
+			false => files(path).into_iter()
+				.map(|f| {
+					let name = stem(&f);
+					Instance {
+						stratum: config.stratum(&name),
+						backend: name.clone(),
+						params:  None,
+						colour:  None,
+						name,
+					}
+				})
+				.collect(),
+		};
+
+		// files with declared instances are only loaded as those
+		let instances = found.into_iter()
+			.filter(|i| !config.instances.iter().any(|d| d.backend == i.backend))
+			.chain(config.instances.iter().cloned()
+				.map(|i| Instance { stratum: i.stratum.clone().or_else(|| config.stratum(&i.name)), ..i }))
+			.filter(|i| config.is_enabled(&[&i.name, &i.backend]))
+			.collect::<Vec<_>>();
+
+		// backends can already ask for theirs while loading
+		let _ = crate::strata::STRATA.set(instances.iter()
+			.filter_map(|i| i.stratum.clone().map(|s| (i.name.clone(), s)))
+			.collect());
+
+		let ns = instances.into_iter()
+			.map(|i| Self::load(rt, path, i))
 			.collect::<Vec<_>>();
 
 		if ns.is_empty() {
-			crate::err!("{}: No frontends found", path.as_ref().display());
+			crate::err!("{path}: No frontends found");
 		}
 
 		Self(ns)
 	}
 
-	// linear search is fine 0 way this is gonna be a bottleneck
+	// every file in `dir`, once for each stratum with its package manager and named like `debian:apt`.
+	// without strata, the ones whose package manager is on `PATH`
+	pub fn discover(rt: &mut janetrs::client::JanetClient, dir: &str) -> Vec<Instance> {
+		let strata = match crate::strata::is_bedrock() {
+			true  => crate::strata::list().into_iter().map(Some).collect(),
+			false => vec![None],
+		};
+
+		files(dir).into_iter()
+			.flat_map(|file| {
+				let bins = detect(rt, &file);
+				strata.iter()
+					.filter(|s| bins.iter().any(|b| crate::strata::provides(s.as_deref(), b)))
+					.map(|s| Instance {
+						name:    s.as_ref().map_or_else(|| stem(&file), |s| format!("{s}:{}", stem(&file))),
+						backend: stem(&file),
+						stratum: s.clone(),
+						params:  None,
+						colour:  None,
+					})
+					.collect::<Vec<_>>()
+			})
+			.collect()
+	}
+
+	// a fresh copy of the file in its own namespace, so instances don't share state
+	fn load(rt: &mut janetrs::client::JanetClient, dir: &str, i: Instance) -> Backend {
+		let file = std::path::absolute(Path::new(dir).join(format!("{}.janet", i.backend))).unwrap();
+		let ns = i.name;
+
+		if !file.is_file() {
+			crate::err!("{ns}: backend `{}` not found in {dir}", i.backend);
+		}
+
+		let env = rt.env().unwrap();
+		let load = match env.resolve("pmm/load").map(|f| f.unwrap()) {
+			Some(TaggedJanet::Function(f)) => f,
+			_ => unreachable!(),
+		};
+
+		load.clone().call([
+			Janet::from(file.to_string_lossy().as_ref()),
+			Janet::from(ns.as_str()),
+			i.params.unwrap_or_else(|| Janet::from(JanetStruct::builder(0).finalize())),
+		]).unwrap_or_else(|e| crate::err!("{ns}: {e}"));
+
+		Backend {
+			caps:   CAPABILITIES.iter()
+				.filter(|c| env.resolve(format!("{ns}/{c}"))
+					.is_some_and(|f| matches!(f.unwrap(), TaggedJanet::Function(_))))
+				.copied()
+				.collect(),
+			path:   file,
+			colour: match (i.colour, env.resolve(format!("{ns}/COLOUR"))) {
+				(Some(c), _) => c,
+				(None, None) => {
+					crate::warn!("{ns}: `COLOUR` not specified, set to `nil` to use the default");
+					(u8::MAX, u8::MAX, u8::MAX)
+				},
+				(None, Some(c)) => c.janet_into(),
+			},
+			name:    ns,
+			stratum: i.stratum,
+		}
+	}
+
+	// linear search is fine 0 way this is gonna be a bottleneck.
+	// takes `apt` as well as `debian:apt`
 	pub fn get(&self, name: &str) -> &Backend {
-		self.0.iter().find(|b| b.name == name)
+		self.0.iter().find(|b| b.name == name || b.qualified() == name)
 			.unwrap_or_else(|| crate::err!("{}: Backend not found", name))
 	}
 }
@@ -70,6 +200,92 @@ impl std::ops::Deref for Backends {
 	fn deref(&self) -> &Self::Target { &self.0 }
 }
 
+// raised by backends with `(pmm/error kind msg &opt data)`, anything else is a `Backend` error
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+	NotFound,
+	Network,
+	Timeout,
+	Backend,
+	Other(String),
+}
+
+impl ErrorKind {
+	// exit codes, see `--help`
+	pub fn code(&self) -> i32 {
+		match self {
+			ErrorKind::NotFound => 2,
+			ErrorKind::Network  => 3,
+			ErrorKind::Timeout  => 4,
+			ErrorKind::Backend  => 5,
+			ErrorKind::Other(_) => 6,
+		}
+	}
+}
+
+impl From<&str> for ErrorKind {
+	fn from(s: &str) -> Self {
+		match s {
+			"not-found" => ErrorKind::NotFound,
+			"network"   => ErrorKind::Network,
+			"timeout"   => ErrorKind::Timeout,
+			"backend"   => ErrorKind::Backend,
+			s => ErrorKind::Other(s.to_string()),
+		}
+	}
+}
+
+impl Display for ErrorKind {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			ErrorKind::NotFound => write!(f, "not found"),
+			ErrorKind::Network  => write!(f, "network"),
+			ErrorKind::Timeout  => write!(f, "timeout"),
+			ErrorKind::Backend  => write!(f, "backend"),
+			ErrorKind::Other(s) => write!(f, "{s}"),
+		}
+	}
+}
+
+pub struct BackendError {
+	pub kind:    ErrorKind,
+	pub message: String,
+	pub data:    Option<String>,
+	pub trace:   Option<String>,
+}
+
+impl BackendError {
+	pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+		Self { kind, message: message.into(), data: None, trace: None }
+	}
+}
+
+impl Display for BackendError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "[{}] {}", self.kind, self.message)
+	}
+}
+
+// `[:ok val]` or `[:error [kind msg data trace]]`, as returned by `pmm/try`
+impl JanetInto<Result<Janet, BackendError>> for Janet {
+	fn janet_into(self) -> Result<Janet, BackendError> {
+		let TaggedJanet::Tuple(t) = self.unwrap() else { unreachable!() };
+
+		match t[0] == keyword![ok] {
+			true => Ok(t[1]),
+			false => {
+				let TaggedJanet::Tuple(e) = t[1].unwrap() else { unreachable!() };
+				Err(BackendError {
+					kind:    ErrorKind::from(e[0].to_string().trim_start_matches(':')),
+					message: e[1].janet_into(),
+					data:    e[2].janet_into(),
+					trace:   e[3].janet_into(),
+				})
+			},
+		}
+	}
+}
+
 // deriving default so we can std::mem:take it later
 #[derive(Default)]
 pub struct Package {
@@ -100,17 +316,17 @@ impl JanetInto<Package> for Janet {
 	}
 }
 
-impl Into<Janet> for &Package {
-	fn into(self) -> Janet {
+impl From<&Package> for Janet {
+	fn from(p: &Package) -> Self {
 		Janet::from(janetrs::structs! {
-			keyword![name]        => self.name.as_str(),
-			keyword![version]     => self.version.as_str(),
-			keyword![alias]       => self.alias.as_ref()
+			keyword![name]        => p.name.as_str(),
+			keyword![version]     => p.version.as_str(),
+			keyword![alias]       => p.alias.as_ref()
 				.map_or_else(Janet::nil, |a| a.as_str().into()),
-			keyword![description] => self.desc.as_str(),
-			keyword![authors]     => self.authors.as_ref()
+			keyword![description] => p.desc.as_str(),
+			keyword![authors]     => p.authors.as_ref()
 				.map_or_else(Janet::nil, |a| Janet::array(a.iter().map(|a| Janet::from(a.as_str())).collect())),
-			keyword![url]         => self.url.as_str(),
+			keyword![url]         => p.url.as_str(),
 		})
 	}
 }
@@ -200,3 +416,71 @@ impl Display for PackageInfo {
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::strata::{fake_tree, set_root, TEST_ROOT};
+
+	fn backends(dir: &std::path::Path, files: &[(&str, &str)]) -> String {
+		std::fs::create_dir_all(dir).unwrap();
+		files.iter().for_each(|(name, src)| std::fs::write(dir.join(format!("{name}.janet")), src).unwrap());
+		dir.to_string_lossy().into_owned()
+	}
+
+	fn discover(dir: &str) -> Vec<(String, String, Option<String>)> {
+		super::Backends::discover(&mut crate::prelude::test_runtime(), dir).into_iter()
+			.map(|i| (i.name, i.backend, i.stratum))
+			.collect()
+	}
+
+	#[test]
+	fn discover_strata() {
+		let _lock = TEST_ROOT.lock().unwrap_or_else(|e| e.into_inner());
+		let root = fake_tree("discover", &[("debian", &["dpkg"]), ("arch", &["pacman"]), ("void", &[])]);
+		set_root(&root);
+
+		let dir = backends(&root.join("backends"), &[
+			("apt", "(def DETECT [\"apt-get\" \"dpkg\"])"),
+			("pacman", ""),
+			("xbps", "(def DETECT \"xbps-install\")"),
+		]);
+
+		let some = |s: &str| Some(String::from(s));
+		assert_eq!(discover(&dir), [
+			(String::from("debian:apt"), String::from("apt"), some("debian")),
+			(String::from("arch:pacman"), String::from("pacman"), some("arch")),
+		]);
+	}
+
+	// without strata, whatever's on `PATH`
+	#[test]
+	fn discover_path() {
+		let _lock = TEST_ROOT.lock().unwrap_or_else(|e| e.into_inner());
+		let root = crate::util::test_dir("discover-path");
+		set_root(root.join("not-bedrock"));
+
+		let dir = backends(&root.join("backends"), &[
+			("shell", "(def DETECT \"sh\")\n(error \"ran at discovery\")"),
+			("nope", "(def DETECT \"pmm-test-not-installed\")"),
+		]);
+
+		assert_eq!(discover(&dir), [(String::from("shell"), String::from("shell"), None)]);
+	}
+
+	// `:backends` overrides apply to instances by their own name
+	#[test]
+	fn instance_stratum() {
+		let mut rt = crate::prelude::test_runtime();
+		let root = crate::util::test_dir("instance-stratum");
+		let dir = backends(&root.join("backends"), &[("apt", "(def COLOUR nil)")]);
+		std::fs::write(root.join("config.janet"), format!("{{:backend-dir {dir:?} :world-path \"world\"
+			:instances [{{:name \"deb\" :backend \"apt\"}} {{:name \"own\" :backend \"apt\" :stratum \"arch\"}}]
+			:backends {{\"deb\" {{:stratum \"debian\"}} \"own\" {{:stratum \"void\"}}}}}}")).unwrap();
+
+		let config = crate::config::Config::eval_from_file(&mut rt, &root.join("config.janet"));
+		let strata = super::Backends::from_dir(&mut rt, &config).iter()
+			.map(|b| (b.name.clone(), b.stratum.clone()))
+			.collect::<Vec<_>>();
+		assert_eq!(strata, [(String::from("deb"), Some(String::from("debian"))), (String::from("own"), Some(String::from("arch")))]);
+	}
+}
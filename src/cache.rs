@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// set up once in `PmmExec::init`, read from the backend threads by `get-req`
+pub static CACHE: OnceLock<Cache> = OnceLock::new();
+
+// `--offline`, or set once a request can't reach its host
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+// oldest cached response served in place of a fresh one, by backend
+static STALE: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+pub fn is_offline() -> bool {
+	OFFLINE.load(Ordering::Relaxed)
+}
+
+pub fn set_offline() {
+	OFFLINE.store(true, Ordering::Relaxed);
+}
+
+pub fn go_offline() {
+	if !OFFLINE.swap(true, Ordering::Relaxed) {
+		crate::warn!("network unreachable, using cached responses only");
+	}
+}
+
+pub fn mark_stale(backend: Option<&str>, age: u64) {
+	let mut stale = STALE.lock().unwrap();
+	let oldest = stale.get_or_insert_default().entry(backend.unwrap_or_default().to_string()).or_default();
+	*oldest = age.max(*oldest);
+}
+
+// how old the stalest response a backend got was, if it got any
+pub fn stale(backend: &str) -> Option<u64> {
+	STALE.lock().unwrap().as_ref().and_then(|s| s.get(backend).copied())
+}
+
+pub fn fmt_age(secs: u64) -> String {
+	match secs {
+		0..60         => format!("{secs}s"),
+		60..3600      => format!("{}m", secs / 60),
+		3600..86400   => format!("{}h", secs / 3600),
+		_             => format!("{}d", secs / 86400),
+	}
+}
+
+pub struct Cache {
+	pub dir:     PathBuf,
+	pub refresh: bool, // `--refresh`, don't read from the cache but still fill it
+	ttl:         Option<u64>,
+	backends:    HashMap<String, u64>,
+}
+
+pub struct Entry {
+	pub fetched:       u64,
+	pub max_age:       Option<u64>,
+	pub etag:          Option<String>,
+	pub last_modified: Option<String>,
+	pub body:          Vec<u8>,
+}
+
+pub fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl Entry {
+	pub fn age(&self) -> u64 {
+		now().saturating_sub(self.fetched)
+	}
+
+	// a configured ttl wins over whatever the server said
+	pub fn is_fresh(&self, ttl: Option<u64>) -> bool {
+		self.age() < ttl.or(self.max_age).unwrap_or(0)
+	}
+}
+
+impl Cache {
+	pub fn new(dir: Option<PathBuf>, refresh: bool, ttl: Option<u64>, backends: HashMap<String, u64>) -> Self {
+		Self { dir: dir.unwrap_or_else(Self::default_dir), refresh, ttl, backends }
+	}
+
+	// $XDG_CACHE_HOME/pmm, or ~/.cache/pmm
+	pub fn default_dir() -> PathBuf {
+		std::env::var_os("XDG_CACHE_HOME")
+			.filter(|d| !d.is_empty())
+			.map(PathBuf::from)
+			.or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+			.unwrap_or_else(std::env::temp_dir)
+			.join("pmm")
+	}
+
+	pub fn ttl(&self, backend: Option<&str>) -> Option<u64> {
+		backend.and_then(|b| self.backends.get(b).copied()).or(self.ttl)
+	}
+
+	// what a response is stored under, the url and every header sent with it.
+	// `url ...` and `header name: value` lines, which start the entry
+	fn key(url: &str, headers: &[(String, String)]) -> String {
+		let mut headers = headers.iter()
+			.map(|(k, v)| format!("header {}: {v}", k.to_lowercase()))
+			.collect::<Vec<_>>();
+		headers.sort();
+
+		std::iter::once(format!("url {url}")).chain(headers).collect::<Vec<_>>().join("\n")
+	}
+
+	// fnv-1a, stable across runs unlike `DefaultHasher`
+	fn path(&self, key: &str) -> PathBuf {
+		let hash = key.bytes().fold(0xcbf29ce484222325_u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+		self.dir.join(format!("{hash:016x}"))
+	}
+
+	// entries are `key value` lines, a blank line, then the body
+	pub fn get(&self, url: &str, headers: &[(String, String)]) -> Option<Entry> {
+		let key = Self::key(url, headers);
+		let file = std::fs::read(self.path(&key)).ok()?;
+		let split = file.windows(2).position(|w| w == b"\n\n")?;
+		let meta = String::from_utf8_lossy(&file[..split]);
+
+		// hash collision
+		let stored = meta.lines().filter(|l| l.starts_with("url ") || l.starts_with("header ")).collect::<Vec<_>>();
+		if stored.join("\n") != key { return None; }
+
+		let meta = meta.lines()
+			.filter_map(|l| l.split_once(' '))
+			.collect::<HashMap<_, _>>();
+
+		Some(Entry {
+			fetched:       meta.get("fetched")?.parse().ok()?,
+			max_age:       meta.get("max-age").and_then(|a| a.parse().ok()),
+			etag:          meta.get("etag").map(|e| e.to_string()),
+			last_modified: meta.get("last-modified").map(|l| l.to_string()),
+			body:          file[split + 2..].to_vec(),
+		})
+	}
+
+	pub fn put(&self, url: &str, headers: &[(String, String)], entry: &Entry) {
+		static TMP: AtomicUsize = AtomicUsize::new(0);
+
+		let key = Self::key(url, headers);
+		let mut file = format!("{key}\nfetched {}\n", entry.fetched);
+		entry.max_age.iter().for_each(|a| file.push_str(&format!("max-age {a}\n")));
+		entry.etag.iter().for_each(|e| file.push_str(&format!("etag {e}\n")));
+		entry.last_modified.iter().for_each(|l| file.push_str(&format!("last-modified {l}\n")));
+		file.push('\n');
+
+		let mut file = file.into_bytes();
+		file.extend_from_slice(&entry.body);
+
+		// written aside and moved into place, other threads and pmms read and write the same entries.
+		// not being able to cache isn't worth failing over
+		let path = self.path(&key);
+		let tmp = path.with_extension(format!("{}-{}", std::process::id(), TMP.fetch_add(1, Ordering::Relaxed)));
+		let _ = std::fs::create_dir_all(&self.dir)
+			.and_then(|_| std::fs::write(&tmp, file))
+			.and_then(|_| std::fs::rename(&tmp, &path))
+			.inspect_err(|e| {
+				let _ = std::fs::remove_file(&tmp);
+				crate::warn!("{}: {e}", self.dir.display());
+			});
+	}
+
+	// removes every entry, returns how many there were
+	pub fn clean(&self) -> std::io::Result<usize> {
+		match std::fs::read_dir(&self.dir) {
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+			Err(e) => Err(e),
+			Ok(dir) => dir.map(|e| e.and_then(|e| std::fs::remove_file(e.path())))
+				.try_fold(0, |n, r| r.map(|_| n + 1)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Cache, Entry};
+
+	#[test]
+	fn keyed_by_headers() {
+		let dir = crate::util::test_dir("cache-headers");
+		let cache = Cache::new(Some(dir.clone()), false, None, Default::default());
+		let h = |k: &str, v: &str| vec![(k.to_string(), v.to_string())];
+		let entry = |body: &str| Entry { fetched: super::now(), max_age: Some(60), etag: None, last_modified: None, body: body.into() };
+
+		cache.put("http://x/a", &h("Accept", "text/plain"), &entry("plain"));
+		cache.put("http://x/a", &[], &entry("default"));
+
+		assert_eq!(cache.get("http://x/a", &h("accept", "text/plain")).unwrap().body, b"plain");
+		assert_eq!(cache.get("http://x/a", &[]).unwrap().body, b"default");
+		assert!(cache.get("http://x/a", &h("Accept", "application/json")).is_none());
+		assert!(cache.get("http://x/b", &[]).is_none());
+
+		// nothing left lying around from the writes
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+	}
+}
@@ -0,0 +1,181 @@
+// shell completion. `pmm completions bash` prints a script that completes actions straight from the
+// command table and asks `pmm __complete` about everything else. that runs on every tab so it loads
+// nothing, backend names and the world file's path are whatever the last real run left behind
+
+use std::path::PathBuf;
+
+use crate::args::{Command, Flag, COMMANDS, FLAGS};
+
+const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+// next to the cache rather than in it, `cache clean` empties that
+fn state_path() -> PathBuf {
+	crate::cache::Cache::default_dir().with_file_name("pmm-completions")
+}
+
+// called once the backends are loaded
+pub fn remember(world: &str, backends: &crate::backend::Backends) {
+	let world = std::path::absolute(world).unwrap_or_else(|_| PathBuf::from(world));
+
+	let state = std::iter::once(format!("world {}", world.display()))
+		.chain(backends.iter().map(|b| format!("backend {}", b.name)))
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	// stale completions aren't worth a warning
+	let _ = std::fs::create_dir_all(state_path().parent().unwrap())
+		.and_then(|_| std::fs::write(state_path(), state));
+}
+
+fn quote(s: &str) -> String {
+	format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn commands() -> impl Iterator<Item = &'static Command> {
+	COMMANDS.iter().filter(|c| !c.hidden)
+}
+
+// `pmm completions <shell>`
+pub fn script(shell: &str) {
+	let script = match shell {
+		"bash" => format!(r#"# pmm completions for bash, `source <(pmm completions bash)`
+_pmm() {{
+	local line=${{COMP_LINE:0:COMP_POINT}} words
+	read -ra words <<< "$line"
+	[[ $line == *[[:space:]] ]] && words+=('')
+
+	local word=${{words[-1]}}
+	if (( ${{#words[@]}} == 2 )) && [[ $word != -* ]]; then
+		COMPREPLY=($(compgen -W {} -- "$word"))
+		return
+	fi
+
+	# bash only replaces what's after the last `=` or `:`
+	local tail=${{word##*[=:]}}
+	local head=${{word:0:${{#word}}-${{#tail}}}}
+	local IFS=$'\n'
+	COMPREPLY=($(pmm __complete -- "${{words[@]:1}}" 2>/dev/null | cut -f1))
+	COMPREPLY=("${{COMPREPLY[@]#"$head"}}")
+
+	# `--backends=` and `cargo/` aren't done yet
+	[[ ${{#COMPREPLY[@]}} == 1 && $COMPREPLY == *[=/] ]] && compopt -o nospace
+}}
+complete -F _pmm pmm"#,
+			quote(&commands().map(|c| c.name).collect::<Vec<_>>().join(" "))),
+
+		"zsh" => format!(r#"#compdef pmm
+# pmm completions for zsh, `pmm completions zsh > "${{fpath[1]}}/_pmm"`
+_pmm() {{
+	local -a described open
+	if (( CURRENT == 2 )) && [[ $PREFIX != -* ]]; then
+		described=({})
+	else
+		local c v
+		for c in "${{(@f)$(pmm __complete -- "${{(@)words[2,CURRENT]}}" 2>/dev/null)}}"; do
+			[[ -z $c ]] && continue
+			v="${{${{c%%$'\t'*}}//:/\\:}}:${{c#*$'\t'}}"
+			# `--backends=` and `cargo/` aren't done yet
+			[[ ${{c%%$'\t'*}} == *[=/] ]] && open+=("$v") || described+=("$v")
+		done
+	fi
+	_describe -t values pmm described
+	_describe -t values pmm open -S ''
+}}
+
+if [[ $zsh_eval_context[-1] == loadautofunc ]]; then
+	_pmm "$@"
+else
+	compdef _pmm pmm
+fi"#,
+			commands().map(|c| quote(&format!("{}:{}", c.name, c.help))).collect::<Vec<_>>().join(" ")),
+
+		"fish" => format!(r#"# pmm completions for fish, `pmm completions fish > ~/.config/fish/completions/pmm.fish`
+complete -c pmm -f
+{}
+complete -c pmm -n 'not __fish_use_subcommand; or string match -q -- "-*" (commandline -ct)' -a '(pmm __complete -- (commandline -opc)[2..] (commandline -ct) 2>/dev/null)'"#,
+			commands().map(|c| format!("complete -c pmm -n __fish_use_subcommand -a {} -d {}", c.name, quote(c.help)))
+				.collect::<Vec<_>>().join("\n")),
+
+		s => crate::err!("Unknown shell `{s}`, expected one of {}", SHELLS.join(", ")),
+	};
+
+	println!("{script}");
+}
+
+// `flag`'s values, the ones given so far in `--flag=a,b` are kept
+fn values(flag: &Flag, head: &str, given: &str, backends: &[&str]) -> Vec<(String, &'static str)> {
+	let (done, _) = given.rsplit_once(',').map_or(("", given), |(d, c)| (d, c));
+	let head = match done {
+		"" => head.to_string(),
+		d  => format!("{head}{d},"),
+	};
+
+	match flag.long {
+		"backends" => backends.iter().map(|b| (format!("{head}{b}"), "")).collect(),
+		"ui"       => ["pmm", "pacman", "apt", "xbps"].iter().map(|u| (format!("{head}{u}"), "")).collect(),
+		_ => Vec::new(),
+	}
+}
+
+// `pmm __complete -- <words>`, what the last of them (maybe empty) could be.
+// one `candidate\tdescription` per line
+pub fn complete(words: &[String]) {
+	let Some((cur, prev)) = words.split_last() else { return };
+
+	let state = std::fs::read_to_string(state_path()).unwrap_or_default();
+	let backends = state.lines().filter_map(|l| l.strip_prefix("backend ")).collect::<Vec<_>>();
+	let world = || state.lines().find_map(|l| l.strip_prefix("world "))
+		.map(crate::world::World::read)
+		.unwrap_or_default();
+
+	let flags = |cmd: Option<&'static Command>| FLAGS.iter().chain(cmd.into_iter().flat_map(|c| c.flags));
+
+	// where in the command table `cur` is, same as `Args::command` would see it
+	let (mut among, mut cmd, mut args): (&[Command], Option<&'static Command>, usize) = (COMMANDS, None, 0);
+	let mut pending: Option<&'static Flag> = None;
+
+	for w in prev {
+		if pending.take().is_some() { continue }
+
+		match w.strip_prefix("--") {
+			Some(l) => pending = flags(cmd).find(|f| f.long == l && f.value.is_some()),
+			None if w.starts_with('-') => {},
+			None => match among.iter().find(|c| c.name == w).filter(|_| args == 0) {
+				Some(c) => (among, cmd) = (c.subs, Some(c)),
+				None    => { among = &[]; args += 1 },
+			},
+		}
+	}
+
+	let candidates = match (pending, cur.strip_prefix("--")) {
+		(Some(f), _) => values(f, "", cur, &backends),
+		(_, Some(l)) => match l.split_once('=') {
+			Some((k, v)) => flags(cmd).find(|f| f.long == k)
+				.map_or_else(Vec::new, |f| values(f, &format!("--{k}="), v, &backends)),
+			None => flags(cmd).map(|f| match f.value {
+				Some(_) => (format!("--{}=", f.long), f.help),
+				None    => (format!("--{}", f.long), f.help),
+			}).collect(),
+		},
+		_ if args == 0 && !among.is_empty() => among.iter()
+			.filter(|c| !c.hidden)
+			.map(|c| (c.name.to_string(), c.help))
+			.collect(),
+		_ => match cmd.map(|c| c.name) {
+			Some("completions") => SHELLS.iter().map(|s| (s.to_string(), "")).collect(),
+			Some("del") => world().into_iter().map(|(p, _)| (p, "")).collect(),
+			// `cargo/ripgrep` only asks `cargo`
+			Some("info") if cur.contains('/') => world().into_iter().map(|(p, b)| (format!("{b}/{p}"), "")).collect(),
+			Some("info") => world().into_iter().map(|(p, _)| (p, ""))
+				.chain(backends.iter().map(|b| (format!("{b}/"), "")))
+				.collect(),
+			Some("add") => backends.iter().map(|b| (format!("{b}/"), "")).collect(),
+			Some("get" | "set" | "unset") if args == 0 => crate::config::KEYS.iter().map(|k| (k.to_string(), "")).collect(),
+			_ => Vec::new(),
+		},
+	};
+
+	candidates.iter()
+		.filter(|(c, _)| c.starts_with(cur.as_str()))
+		.for_each(|(c, d)| println!("{c}\t{d}"));
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
-use janetrs::{JanetKeyword, TaggedJanet};
+use std::path::{Path, PathBuf};
+use janetrs::{Janet, JanetKeyword, JanetStruct, TaggedJanet};
 
 #[derive(Debug)]
 pub struct Config {
@@ -7,45 +8,483 @@ pub struct Config {
 	pub world_path:   String,
 	pub env:          HashMap<String, String>,
 	pub priority:     Vec<String>,
+	pub enabled:      Option<Vec<String>>,
+	pub disabled:     Vec<String>,
+	pub timeout:      Option<f64>,
+	pub cache_dir:    Option<String>,
+	pub cache_ttl:    Option<f64>,
+	pub escalate:     Option<Vec<String>>,
+	pub bedrock_root: Option<String>,
+	pub discover:     bool,
+	pub ui:           crate::ui::Flavour,
+	pub backends:     HashMap<String, BackendConfig>,
+	pub instances:    Vec<Instance>,
+	pub hooks:        HashMap<&'static str, Janet>, // functions from `HOOKS`
+	_root:            janetrs::JanetGcRootGuard,    // the config, keeping `hooks` and `params` alive
+}
+
+// per-backend overrides, `:backends {"cargo" {...}}`
+#[derive(Debug, Default)]
+pub struct BackendConfig {
+	pub timeout:   Option<f64>,
+	pub cache_ttl: Option<f64>,
+	pub env:       HashMap<String, String>,
+	pub stratum:   Option<String>,
+}
+
+// a backend loaded from `backend`'s file under its own name,
+// `:instances [{:name "deb-stable" :backend "apt" :params {:suite "stable"}}]`
+#[derive(Debug, Clone)]
+pub struct Instance {
+	pub name:    String,
+	pub backend: String,
+	pub stratum: Option<String>,
+	pub params:  Option<Janet>, // `PARAMS` in the backend, alive as long as the `Config`
+	pub colour:  Option<(u8, u8, u8)>,
+}
+
+// `$VAR`, `${VAR}` and `${VAR:-default}` from the environment pmm was started with
+fn expand(s: &str) -> Result<String, String> {
+	let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+	let mut out = String::with_capacity(s.len());
+	let mut rest = s;
+
+	while let Some(i) = rest.find('$') {
+		out.push_str(&rest[..i]);
+		rest = &rest[i + 1..];
+
+		match rest.strip_prefix('{') {
+			Some(r) => {
+				// defaults can have their own `${...}`
+				let end = r.char_indices()
+					.scan(1, |depth, (i, c)| {
+						*depth += match c { '{' => 1, '}' => -1, _ => 0 };
+						Some((i, *depth))
+					})
+					.find_map(|(i, depth)| (depth == 0).then_some(i))
+					.ok_or_else(|| format!("`{s}`: unclosed `${{`"))?;
+				let (name, default) = r[..end].split_once(":-").unwrap_or((&r[..end], ""));
+				out.push_str(&var(name).map_or_else(|| expand(default), Ok)?);
+				rest = &r[end + 1..];
+			},
+			None => {
+				let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+				match end {
+					0 => out.push('$'),
+					_ => out.push_str(&var(&rest[..end]).unwrap_or_default()),
+				}
+				rest = &rest[end..];
+			},
+		}
+	}
+
+	out.push_str(rest);
+	Ok(out)
+}
+
+// $XDG_CONFIG_HOME/pmm/config.janet, or ~/.config/pmm/config.janet
+fn user_path() -> Option<PathBuf> {
+	std::env::var_os("XDG_CONFIG_HOME")
+		.filter(|d| !d.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+		.map(|d| d.join("pmm/config.janet"))
+}
+
+const SYSTEM_PATH: &str = "/etc/pmm/config.janet";
+
+// `$PMM_CONFIG` or `--config`, which have to exist when given
+fn explicit(flag: Option<&str>) -> Option<PathBuf> {
+	std::env::var_os("PMM_CONFIG")
+		.filter(|p| !p.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| flag.map(PathBuf::from))
+}
+
+// `$PMM_CONFIG`, `--config`, then the user's and the system's
+pub fn find(flag: Option<&str>) -> PathBuf {
+	explicit(flag).unwrap_or_else(|| {
+		let chain = user_path().into_iter().chain([PathBuf::from(SYSTEM_PATH)]).collect::<Vec<_>>();
+
+		chain.iter().find(|p| p.is_file()).cloned().unwrap_or_else(|| crate::err!(
+			"No config found in {}\nRun `pmm init` to write one",
+			chain.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(" or ")))
+	})
+}
+
+// where `pmm init` writes one
+pub fn target(flag: Option<&str>) -> PathBuf {
+	explicit(flag).or_else(user_path)
+		.unwrap_or_else(|| crate::err!("Nowhere to write a config, set `$PMM_CONFIG` or pass `--config`"))
+}
+
+// the config with its includes merged in, paths resolved and `:env` as `exec` gets it, as a struct
+pub fn eval(rt: &mut janetrs::client::JanetClient, path: &Path) -> JanetStruct<'static> {
+	let config = try_eval(rt, path).unwrap_or_else(|e| crate::err!("{}: {e}", path.display()));
+	let key = |k: &Janet| k.to_string().trim_start_matches(':').to_string();
+
+	// values that don't expand are left for `check` to complain about
+	let env = |v: &Janet| match v.unwrap() {
+		TaggedJanet::Struct(s) => s.iter()
+			.map(|(k, v)| (*k, match v.unwrap() {
+				TaggedJanet::String(s) => expand(&s.to_string()).map_or(*v, |e| Janet::from(e.as_str())),
+				_ => *v,
+			}))
+			.collect::<JanetStruct>().into(),
+		_ => *v,
+	};
+
+	config.iter()
+		.map(|(k, v)| (*k, match (key(k).as_str(), v.unwrap()) {
+			("env", _) => env(v),
+			("backends", TaggedJanet::Struct(b)) => b.iter()
+				.map(|(name, b)| (*name, match b.unwrap() {
+					TaggedJanet::Struct(b) => b.iter()
+						.map(|(k, v)| (*k, if key(k) == "env" { env(v) } else { *v }))
+						.collect::<JanetStruct>().into(),
+					_ => *b,
+				}))
+				.collect::<JanetStruct>().into(),
+			_ => *v,
+		}))
+		.collect()
+}
+
+fn try_eval(rt: &mut janetrs::client::JanetClient, path: &Path) -> Result<JanetStruct<'static>, String> {
+	let abs = std::fs::metadata(path).and_then(|_| std::path::absolute(path)).map_err(|e| e.to_string())?;
+
+	match rt.run(format!("(pmm/config {:?})", abs.to_string_lossy())).map(|v| v.unwrap()) {
+		Err(e) => Err(e.to_string()),
+		Ok(TaggedJanet::Struct(m)) => Ok(m),
+		Ok(t) => Err(format!("Expected `map`, got `{}`", t.kind())),
+	}
+}
+
+// a top-level key, as `timeout` or `:timeout`
+pub fn key(name: &str) -> &str {
+	let key = name.trim_start_matches(':');
+	if KEYS.contains(&key) {
+		return key;
+	}
+
+	match crate::util::closest(key, KEYS.iter().copied()) {
+		Some(c) => crate::err!("Unknown key `:{key}`, did you mean `:{c}`?"),
+		None    => crate::err!("Unknown key `:{key}`"),
+	}
+}
+
+// write `text` as the config at `path` if it checks out. it's tried as a file next to it
+// first, so includes and relative paths mean what they will once it's written
+pub fn save(rt: &mut janetrs::client::JanetClient, path: &Path, text: &str) {
+	let name = path.file_name().unwrap_or_default().to_string_lossy();
+	let tmp = path.with_file_name(format!(".{name}.pmm-edit"));
+
+	std::fs::write(&tmp, text).unwrap_or_else(|e| crate::err!("{}: {e}", tmp.display()));
+	let checked = Config::check(rt, &tmp);
+	let _ = std::fs::remove_file(&tmp);
+
+	if let Err(e) = checked {
+		crate::err!("{}: Left as it was, the change would make it invalid: {e}", path.display());
+	}
+
+	// in place, it may well be a symlink into someone's dotfiles
+	std::fs::write(path, text).unwrap_or_else(|e| crate::err!("{}: {e}", path.display()));
+}
+
+pub const KEYS: &[&str] = &[
+	"backend-dir", "world-path", "env", "priority", "enabled", "disabled", "timeout", "cache-dir", "cache-ttl",
+	"escalate", "bedrock-root", "discover", "ui", "backends", "instances",
+	"before-add", "after-add", "before-del", "pick", "filter",
+];
+// config keys holding functions pmm calls, see `PmmExec::hook`
+const HOOKS: &[&str] = &["before-add", "after-add", "before-del", "pick", "filter"];
+const BACKEND_KEYS: &[&str] = &["timeout", "cache-ttl", "env", "stratum"];
+const INSTANCE_KEYS: &[&str] = &["name", "backend", "stratum", "params", "colour"];
+
+// everything wrong with the config, reported together once all of it has been looked at.
+// each check notes what's wrong under the key's path and gives back what it could make of it
+#[derive(Default)]
+struct Problems(std::cell::RefCell<Vec<String>>);
+
+impl Problems {
+	fn add(&self, key: &str, msg: impl std::fmt::Display) {
+		self.0.borrow_mut().push(match key {
+			"" => msg.to_string(),
+			k  => format!("`{k}`: {msg}"),
+		});
+	}
+
+	fn expected<T>(&self, key: &str, what: &str, v: &Janet) -> Option<T> {
+		self.add(key, format!("Expected `{what}`, got `{}`", v.kind()));
+		None
+	}
+
+	fn string(&self, key: &str, v: Option<&Janet>) -> Option<String> {
+		match v?.unwrap() {
+			TaggedJanet::String(s) => Some(s.to_string()),
+			_ => self.expected(key, "string", v?),
+		}
+	}
+
+	fn required(&self, key: &str, v: Option<&Janet>) -> String {
+		if v.is_none() {
+			self.add(key, "missing");
+		}
+		self.string(key, v).unwrap_or_default()
+	}
+
+	fn number(&self, key: &str, v: Option<&Janet>) -> Option<f64> {
+		match v?.unwrap() {
+			TaggedJanet::Number(n) => Some(n),
+			_ => self.expected(key, "number", v?),
+		}
+	}
+
+	// `:timeout` and `:cache-ttl`
+	fn seconds(&self, key: &str, v: Option<&Janet>) -> Option<f64> {
+		match self.number(key, v)? {
+			n if n.is_finite() && n >= 0.0 => Some(n),
+			n => { self.add(key, format!("Expected seconds, 0 or more, got `{n}`")); None },
+		}
+	}
+
+	fn boolean(&self, key: &str, v: Option<&Janet>) -> Option<bool> {
+		match v?.unwrap() {
+			TaggedJanet::Boolean(b) => Some(b),
+			_ => self.expected(key, "boolean", v?),
+		}
+	}
+
+	fn function(&self, key: &str, v: Option<&Janet>) -> Option<Janet> {
+		match v?.unwrap() {
+			TaggedJanet::Function(_) | TaggedJanet::CFunction(_) => v.copied(),
+			_ => self.expected(key, "function", v?),
+		}
+	}
+
+	fn list(&self, key: &str, v: Option<&Janet>) -> Option<Vec<Janet>> {
+		match v?.unwrap() {
+			TaggedJanet::Tuple(t) => Some(t.iter().copied().collect()),
+			TaggedJanet::Array(a) => Some(a.iter().copied().collect()),
+			_ => self.expected(key, "tuple", v?),
+		}
+	}
+
+	fn strings(&self, key: &str, v: Option<&Janet>) -> Option<Vec<String>> {
+		self.list(key, v).map(|l| l.iter().enumerate()
+			.filter_map(|(i, v)| self.string(&format!("{key} {i}"), Some(v)))
+			.collect())
+	}
+
+	// keyword keys, and only the `known` ones
+	fn map(&self, key: &str, v: Option<&Janet>, known: &[&str]) -> Option<JanetStruct<'static>> {
+		match v?.unwrap() {
+			TaggedJanet::Struct(s) => { self.keys(key, &s, known); Some(s) },
+			_ => self.expected(key, "map", v?),
+		}
+	}
+
+	fn keys(&self, key: &str, s: &JanetStruct, known: &[&str]) {
+		s.iter().for_each(|(k, _)| match k.unwrap() {
+			TaggedJanet::Keyword(k) if known.contains(&k.to_string().trim_start_matches(':')) => {},
+			TaggedJanet::Keyword(k) => {
+				let k = k.to_string().trim_start_matches(':').to_string();
+				match crate::util::closest(&k, known.iter().copied()) {
+					Some(c) => self.add(key, format!("Unknown key `:{k}`, did you mean `:{c}`?")),
+					None    => self.add(key, format!("Unknown key `:{k}`")),
+				}
+			},
+			t => self.add(key, format!("Expected `keyword` keys, got `{}` `{k}`", t.kind())),
+		});
+	}
+
+	// string keys, anything goes
+	fn table(&self, key: &str, v: Option<&Janet>) -> Vec<(String, Janet)> {
+		let Some(v) = v else { return Vec::new() };
+		let TaggedJanet::Struct(s) = v.unwrap() else { return self.expected(key, "map", v).unwrap_or_default() };
+
+		s.iter().filter_map(|(k, v)| match k.unwrap() {
+			TaggedJanet::String(k) => Some((k.to_string(), *v)),
+			t => { self.add(key, format!("Expected `string` keys, got `{}`", t.kind())); None },
+		}).collect()
+	}
+
+	fn env(&self, key: &str, v: Option<&Janet>) -> HashMap<String, String> {
+		self.table(key, v).into_iter()
+			.filter_map(|(k, v)| {
+				let key = format!("{key} {k}");
+				self.string(&key, Some(&v))
+					.and_then(|v| expand(&v).map_err(|e| self.add(&key, e)).ok())
+					.map(|v| (k, v))
+			})
+			.collect()
+	}
+
+	fn colour(&self, key: &str, v: Option<&Janet>) -> Option<(u8, u8, u8)> {
+		let l = self.list(key, v)?;
+		let c = l.iter()
+			.filter_map(|c| match c.unwrap() {
+				TaggedJanet::Number(n) if (0.0..=255.0).contains(&n) => Some(n as u8),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+
+		match c[..] {
+			[r, g, b] if l.len() == 3 => Some((r, g, b)),
+			_ => { self.add(key, "Expected `[r g b]` with each from 0 to 255"); None },
+		}
+	}
+
+	fn instances(&self, v: Option<&Janet>) -> Vec<Instance> {
+		let instances = self.list(":instances", v).unwrap_or_default().iter().enumerate()
+			.filter_map(|(i, v)| {
+				let key = format!(":instances {i}");
+				let s = self.map(&key, Some(v), INSTANCE_KEYS)?;
+				let field = |k: &str| s.get(JanetKeyword::new(k));
+
+				Some(Instance {
+					name:    self.required(&format!("{key} :name"), field("name")),
+					backend: self.required(&format!("{key} :backend"), field("backend")),
+					stratum: self.string(&format!("{key} :stratum"), field("stratum")),
+					params:  field("params").copied(),
+					colour:  self.colour(&format!("{key} :colour"), field("colour")),
+				})
+			})
+			.collect::<Vec<_>>();
+
+		instances.iter().enumerate()
+			.filter(|(i, a)| instances[..*i].iter().any(|b| b.name == a.name))
+			.for_each(|(_, a)| self.add(":instances", format!("`{}` declared twice", a.name)));
+
+		instances
+	}
 }
 
 impl Config {
-	pub fn eval_from_file(rt: &mut janetrs::client::JanetClient, path: &str) -> Config {
-		let file = std::fs::read_to_string(path)
-			.unwrap_or_else(|e| crate::err!("{path}: {e}"));
-
-		let config = match rt.run(file).map(|v| v.unwrap()) {
-			Err(e) => crate::err!("{path}: {e}"),
-			Ok(TaggedJanet::Struct(m)) => m,
-			Ok(t) => crate::err!("{path}: Expected `map`, got `{}`", t.kind()),
+	pub fn eval_from_file(rt: &mut janetrs::client::JanetClient, path: &Path) -> Config {
+		Self::check(rt, path).unwrap_or_else(|e| crate::err!("{}: {e}", path.display()))
+	}
+
+	fn check(rt: &mut janetrs::client::JanetClient, path: &Path) -> Result<Config, String> {
+		let config = try_eval(rt, path)?;
+		let p = Problems::default();
+
+		p.keys("", &config, KEYS);
+		let get = |key: &str| config.get(JanetKeyword::new(key));
+
+		let config = Self {
+			backend_dir: p.required(":backend-dir", get("backend-dir")),
+			world_path:  p.required(":world-path", get("world-path")),
+
+			env: p.env(":env", get("env")),
+
+			priority: p.strings(":priority", get("priority")).unwrap_or_default(),
+			enabled:  p.strings(":enabled", get("enabled")),
+			disabled: p.strings(":disabled", get("disabled")).unwrap_or_default(),
+			timeout:  p.seconds(":timeout", get("timeout")),
+
+			cache_dir: p.string(":cache-dir", get("cache-dir")),
+			cache_ttl: p.seconds(":cache-ttl", get("cache-ttl")),
+
+			// `"doas"`, or with arguments `["sudo" "-E"]`
+			escalate: get("escalate").and_then(|v| match v.unwrap() {
+				TaggedJanet::String(s) => Some(vec![s.to_string()]),
+				TaggedJanet::Tuple(_) | TaggedJanet::Array(_) => p.strings(":escalate", Some(v)),
+				_ => p.expected(":escalate", "string` or `tuple", v),
+			}),
+
+			bedrock_root: p.string(":bedrock-root", get("bedrock-root")),
+			discover: p.boolean(":discover", get("discover")).unwrap_or_default(),
+
+			ui: p.string(":ui", get("ui")).and_then(|u| crate::ui::Flavour::parse(&u).or_else(|| {
+				p.add(":ui", format!("Unknown flavour `{u}`, expected `pmm`, `pacman`, `apt` or `xbps`"));
+				None
+			})).unwrap_or_default(),
+
+			backends: p.table(":backends", get("backends")).into_iter()
+				.map(|(k, v)| {
+					let key = format!(":backends {k}");
+					let b = p.map(&key, Some(&v), BACKEND_KEYS);
+					let field = |f: &str| b.as_ref().and_then(|b| b.get(JanetKeyword::new(f)));
+
+					(k, BackendConfig {
+						timeout:   p.seconds(&format!("{key} :timeout"), field("timeout")),
+						cache_ttl: p.seconds(&format!("{key} :cache-ttl"), field("cache-ttl")),
+						env:       p.env(&format!("{key} :env"), field("env")),
+						stratum:   p.string(&format!("{key} :stratum"), field("stratum")),
+					})
+				})
+				.collect(),
+
+			instances: p.instances(get("instances")),
+
+			hooks: HOOKS.iter()
+				.filter_map(|h| p.function(&format!(":{h}"), get(h)).map(|f| (*h, f)))
+				.collect(),
+
+			_root: janetrs::JanetGc::obtain().root(Janet::from(config)),
 		};
 
-		Self {
-			backend_dir: config.get(JanetKeyword::new("backend-dir"))
-				.unwrap_or_else(|| crate::err!("{path}: missing field `:backend-dir`"))
-				.to_string(),
-
-			world_path: config.get(JanetKeyword::new("world-path"))
-				.unwrap_or_else(|| crate::err!("{path}: missing field `:world-path`"))
-				.to_string(),
-
-			env: config.get(JanetKeyword::new("env"))
-				.map_or_else(|| Default::default(), |v| 
-					match v.unwrap() {
-					TaggedJanet::Struct(s) => s.into_iter()
-						.map(|(k, v)| (k.to_string(), v.to_string()))
-						.collect(),
-					t => crate::err!("{path}: Expected `map`, got `{}`", t.kind()),
-				}),
-
-			priority: config.get(JanetKeyword::new("priority"))
-				.map_or_else(|| Default::default(), |v| 
-					match v.unwrap() {
-					TaggedJanet::Tuple(a) => a.into_iter()
-						.map(|v| v.to_string())
-						.collect(),
-					t => crate::err!("{path}: Expected `tuple`, got `{}`", t.kind()),
-				}),
+		let problems = p.0.into_inner();
+		match problems.len() {
+			0 => Ok(config),
+			1 => Err(problems[0].clone()),
+			n => Err(format!("{n} problems\n  {}", problems.join("\n  "))),
 		}
 	}
+
+	// only once the backends are loaded, and a warning since they come and go with the system
+	pub fn check_priority(&self, backends: &crate::backend::Backends) {
+		let names = backends.iter().flat_map(|b| [b.name.clone(), b.qualified()]).collect::<Vec<_>>();
+
+		self.priority.iter()
+			.filter(|p| !names.contains(p) && !self.disabled.contains(p))
+			.for_each(|p| match crate::util::closest(p, names.iter().map(String::as_str)) {
+				Some(c) => crate::warn!("`:priority`: `{p}` isn't a loaded backend, did you mean `{c}`?"),
+				None    => crate::warn!("`:priority`: `{p}` isn't a loaded backend"),
+			});
+	}
+
+	pub fn hook(&self, name: &str) -> Option<Janet> {
+		self.hooks.get(name).copied()
+	}
+
+	// an instance's own `:stratum` comes first
+	pub fn stratum(&self, backend: &str) -> Option<String> {
+		self.backends.get(backend).and_then(|b| b.stratum.clone())
+	}
+
+	pub fn timeout(&self, backend: &str) -> Option<f64> {
+		self.backends.get(backend).and_then(|b| b.timeout).or(self.timeout)
+	}
+
+	// `:env`, with the backend's own on top
+	pub fn env(&self, backend: &str) -> HashMap<String, String> {
+		self.env.iter()
+			.chain(self.backends.get(backend).into_iter().flat_map(|b| &b.env))
+			.map(|(k, v)| (k.clone(), v.clone()))
+			.collect()
+	}
+
+	// by any of its names, `debian:apt` and `apt`
+	pub fn is_enabled(&self, names: &[&str]) -> bool {
+		self.enabled.as_ref().is_none_or(|e| e.iter().any(|b| names.contains(&b.as_str())))
+			&& !self.disabled.iter().any(|b| names.contains(&b.as_str()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn seconds() {
+		let mut rt = crate::prelude::test_runtime();
+		let path = crate::util::test_dir("config-seconds").join("config.janet");
+		std::fs::write(&path, "{:backend-dir \".\" :world-path \"world\" :timeout -1 :cache-ttl (/ 0 0)
+			:backends {\"cargo\" {:timeout math/inf :cache-ttl 0}}}").unwrap();
+
+		let err = super::Config::check(&mut rt, &path).err().unwrap();
+		assert!(err.starts_with("3 problems"), "{err}");
+		[":timeout`", ":cache-ttl`", ":backends cargo :timeout`"].iter()
+			.for_each(|k| assert!(err.contains(&format!("`{k}: Expected seconds")), "{k}: {err}"));
+	}
 }
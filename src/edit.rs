@@ -0,0 +1,343 @@
+// `pmm config set` and `unset`. the config is edited as text so comments and layout stay put, which only
+// works when it's a plain `{...}` written out by hand. anything computed is left for the user to edit
+
+use std::ops::Range;
+use std::path::Path;
+
+// a form in the source, by byte offsets
+struct Form {
+	start: usize,
+	end:   usize,
+	kind:  Kind,
+}
+
+enum Kind {
+	Atom,
+	Str,
+	// `(`, `[` or `{`, and whether it had an `@`
+	Coll(char, bool, Vec<Form>),
+	// `'x`, `;x` and the like
+	Macro,
+}
+
+impl Form {
+	// data that means the same thing as it reads, nothing that gets evaluated into something else
+	fn literal(&self, src: &str) -> bool {
+		match &self.kind {
+			Kind::Str => true,
+			Kind::Atom => !symbol(&src[self.start..self.end]),
+			Kind::Coll(c, _, forms) => *c != '(' && forms.iter().all(|f| f.literal(src)),
+			Kind::Macro => false,
+		}
+	}
+}
+
+fn symbol(atom: &str) -> bool {
+	let digits = atom.trim_start_matches(['+', '-']).trim_start_matches('.');
+	!(atom.starts_with(':') || ["true", "false", "nil"].contains(&atom) || digits.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+struct Reader<'s> {
+	src: &'s str,
+	pos: usize,
+}
+
+impl Reader<'_> {
+	fn peek(&self) -> Option<char> {
+		self.src[self.pos..].chars().next()
+	}
+
+	// whitespace and comments
+	fn skip(&mut self) {
+		while let Some(c) = self.peek() {
+			match c {
+				'#' => self.pos = self.src[self.pos..].find('\n').map_or(self.src.len(), |i| self.pos + i),
+				' ' | '\t' | '\n' | '\r' | '\0' | '\x0b' | '\x0c' => self.pos += 1,
+				_ => break,
+			}
+		}
+	}
+
+	fn form(&mut self) -> Result<Form, (usize, String)> {
+		let start = self.pos;
+		let at = self.src[start..].starts_with('@');
+		if at { self.pos += 1 }
+
+		let kind = match self.peek() {
+			Some('"') => {
+				self.pos += 1;
+				let mut chars = self.src[self.pos..].char_indices();
+				let end = loop {
+					match chars.next() {
+						Some((_, '\\')) => { chars.next(); },
+						Some((i, '"'))  => break i,
+						Some(_) => {},
+						None => return Err((start, String::from("Unterminated string"))),
+					}
+				};
+				self.pos += end + 1;
+				Kind::Str
+			},
+			// as many backticks close it as opened it
+			Some('`') => {
+				let ticks = self.src[self.pos..].len() - self.src[self.pos..].trim_start_matches('`').len();
+				let delim = &self.src[self.pos..self.pos + ticks];
+				let end = self.src[self.pos + ticks..].find(delim)
+					.ok_or_else(|| (start, String::from("Unterminated string")))?;
+				self.pos += ticks + end + ticks;
+				Kind::Str
+			},
+			Some(c @ ('(' | '[' | '{')) => {
+				let close = match c { '(' => ')', '[' => ']', _ => '}' };
+				self.pos += 1;
+
+				let mut forms = Vec::new();
+				loop {
+					self.skip();
+					match self.peek() {
+						Some(d) if d == close => { self.pos += 1; break },
+						Some(_) => forms.push(self.form()?),
+						None => return Err((start, format!("Unclosed `{c}`"))),
+					}
+				}
+				Kind::Coll(c, at, forms)
+			},
+			Some(c @ ('\'' | '~' | ';' | ',' | '|')) if !at => {
+				self.pos += 1;
+				self.skip();
+				if self.peek().is_none() {
+					return Err((start, format!("Nothing after `{c}`")));
+				}
+				self.form()?;
+				Kind::Macro
+			},
+			_ => {
+				let len = self.src[self.pos..]
+					.find(|c: char| c.is_whitespace() || "()[]{}\"`'~;,|#".contains(c))
+					.unwrap_or(self.src.len() - self.pos);
+				if len == 0 && !at {
+					return Err((start, format!("Unexpected `{}`", self.peek().unwrap())));
+				}
+				self.pos += len;
+				Kind::Atom
+			},
+		};
+
+		Ok(Form { start, end: self.pos, kind })
+	}
+
+	fn forms(mut self) -> Result<Vec<Form>, (usize, String)> {
+		let mut forms = Vec::new();
+		loop {
+			self.skip();
+			if self.peek().is_none() { return Ok(forms) }
+			forms.push(self.form()?);
+		}
+	}
+}
+
+fn line(src: &str, pos: usize) -> usize {
+	src[..pos].matches('\n').count() + 1
+}
+
+// a command line argument as the Janet to write. literals go in as they are and
+// anything else that isn't code is taken to be a string, `./world` or `sudo -E`
+pub fn value(arg: &str) -> Result<String, String> {
+	let forms = Reader { src: arg, pos: 0 }.forms();
+	match forms.as_deref() {
+		Ok([f]) if f.literal(arg) => Ok(arg[f.start..f.end].to_string()),
+		Ok([f]) if matches!(f.kind, Kind::Coll(..) | Kind::Macro) =>
+			Err(format!("`{arg}` isn't plain data, quote any strings in it or edit the config by hand")),
+		_ => Ok(quote(arg)),
+	}
+}
+
+fn quote(s: &str) -> String {
+	let escaped = s.chars().map(|c| match c {
+		'"'  => String::from("\\\""),
+		'\\' => String::from("\\\\"),
+		'\n' => String::from("\\n"),
+		'\t' => String::from("\\t"),
+		c if c.is_control() => format!("\\x{:02x}", c as u32),
+		c => c.to_string(),
+	}).collect::<String>();
+
+	format!("\"{escaped}\"")
+}
+
+pub struct Source {
+	text:  String,
+	open:  usize, // just inside the `{`
+	pairs: Vec<(String, Range<usize>, Range<usize>)>, // key, and where it and its value are
+}
+
+impl Source {
+	pub fn read(path: &Path) -> Self {
+		let text = std::fs::read_to_string(path).unwrap_or_else(|e| crate::err!("{}: {e}", path.display()));
+		Self::parse(text).unwrap_or_else(|(pos, e)| match pos {
+			Some(pos) => crate::err!("{}:{pos}: {e}", path.display()),
+			None => crate::err!("{}: {e}", path.display()),
+		})
+	}
+
+	// errors with the line they're on, if there's one to point at
+	fn parse(text: String) -> Result<Self, (Option<usize>, String)> {
+		let by_hand = "can't edit it safely, edit it by hand instead";
+
+		let forms = Reader { src: &text, pos: 0 }.forms()
+			.map_err(|(pos, e)| (Some(line(&text, pos)), e))?;
+
+		let (open, forms) = match &forms[..] {
+			[Form { start, kind: Kind::Coll('{', false, forms), .. }] => (start + 1, forms),
+			_ => return Err((None, format!("Not just a `{{...}}`, {by_hand}"))),
+		};
+
+		if let Some(f) = forms.iter().find(|f| matches!(f.kind, Kind::Macro)) {
+			return Err((Some(line(&text, f.start)), format!("`{}` is computed, {by_hand}", &text[f.start..f.end])));
+		}
+		if forms.len() % 2 == 1 {
+			return Err((None, format!("A key without a value, {by_hand}")));
+		}
+
+		let mut pairs: Vec<(String, Range<usize>, Range<usize>)> = Vec::new();
+		let mut forms = forms.iter();
+		while let (Some(k), Some(v)) = (forms.next(), forms.next()) {
+			let key = &text[k.start..k.end];
+			let name = match (&k.kind, key.strip_prefix(':')) {
+				(Kind::Atom, Some(name)) => name.to_string(),
+				_ => return Err((Some(line(&text, k.start)), format!("`{key}` isn't a plain keyword, {by_hand}"))),
+			};
+
+			if pairs.iter().any(|(n, ..)| *n == name) {
+				return Err((Some(line(&text, k.start)), format!("`{key}` is there twice, {by_hand}")));
+			}
+
+			pairs.push((name, k.start..k.end, v.start..v.end));
+		}
+
+		Ok(Self { text, open, pairs })
+	}
+
+	fn line_start(&self, pos: usize) -> usize {
+		self.text[..pos].rfind('\n').map_or(0, |i| i + 1)
+	}
+
+	fn line_end(&self, pos: usize) -> usize {
+		self.text[pos..].find('\n').map_or(self.text.len(), |i| pos + i)
+	}
+
+	// the value replaced where it is, or a new line under the last key
+	pub fn set(&self, key: &str, value: &str) -> String {
+		let t = &self.text;
+
+		if let Some((_, _, v)) = self.pairs.iter().find(|(k, ..)| k == key) {
+			return format!("{}{value}{}", &t[..v.start], &t[v.end..]);
+		}
+
+		let Some((_, k, v)) = self.pairs.last() else {
+			return format!("{}:{key} {value}{}", &t[..self.open], &t[self.open..]);
+		};
+
+		// lined up with the first key on the last one's line, after its comment if it has one
+		let start = self.line_start(k.start);
+		let first = self.pairs.iter().find(|(_, k, _)| k.start >= start).map_or(k.start, |(_, k, _)| k.start);
+		let indent = t[start..first].chars()
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect::<String>();
+		let rest = &t[v.end..self.line_end(v.end)];
+		let at = match rest.trim_start().is_empty() || rest.trim_start().starts_with('#') {
+			true  => v.end + rest.len(),
+			false => v.end,
+		};
+
+		format!("{}\n{indent}:{key} {value}{}", &t[..at], &t[at..])
+	}
+
+	// a line of its own goes entirely, one ending the map hands the `}` back to the line before.
+	// otherwise whatever follows moves up into its place. comments are kept either way
+	pub fn unset(&self, key: &str) -> Option<String> {
+		let t = &self.text;
+		let i = self.pairs.iter().position(|(n, ..)| n == key)?;
+		let (_, k, v) = &self.pairs[i];
+		let prev = i.checked_sub(1).map_or(self.open, |i| self.pairs[i].2.end);
+
+		let (start, end) = (self.line_start(k.start), self.line_end(v.end));
+		let after = t[v.end..end].trim();
+		let (from, to) = match (t[start..k.start].trim().is_empty(), after) {
+			(true, "") => (start, (end + 1).min(t.len())),
+			(true, a) if !a.starts_with('#') && t[prev..k.start].trim().is_empty() => (prev, v.end),
+			_ => (k.start, t.len() - t[v.end..].trim_start().len()),
+		};
+
+		Some(format!("{}{}", &t[..from], &t[to..]))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{value, Source};
+
+	fn source(text: &str) -> Source {
+		Source::parse(text.to_string()).unwrap_or_else(|(_, e)| panic!("{text:?}: {e}"))
+	}
+
+	fn refused(text: &str) -> String {
+		Source::parse(text.to_string()).err().unwrap_or_else(|| panic!("{text:?} was accepted")).1
+	}
+
+	#[test]
+	fn key_on_open_line() {
+		let s = source("{:a 1\n :b 2}");
+		assert_eq!(s.set("a", "9"), "{:a 9\n :b 2}");
+		assert_eq!(s.set("c", "3"), "{:a 1\n :b 2\n :c 3}");
+		assert_eq!(s.unset("a").unwrap(), "{:b 2}");
+	}
+
+	#[test]
+	fn close_after_last_key() {
+		let s = source("{:a 1\n :b 2}");
+		assert_eq!(s.unset("b").unwrap(), "{:a 1}");
+		assert_eq!(s.set("b", "\"x\""), "{:a 1\n :b \"x\"}");
+	}
+
+	#[test]
+	fn trailing_comment() {
+		let s = source("{\n :a 1 # one\n :b 2 # two\n}");
+		assert_eq!(s.set("c", "3"), "{\n :a 1 # one\n :b 2 # two\n :c 3\n}");
+		assert_eq!(s.set("a", "9"), "{\n :a 9 # one\n :b 2 # two\n}");
+		assert_eq!(s.unset("a").unwrap(), "{\n # one\n :b 2 # two\n}");
+	}
+
+	#[test]
+	fn multi_line_value() {
+		let s = source("{:a 1\n :b {:x 1\n     :y 2}\n}");
+		assert_eq!(s.set("b", "nil"), "{:a 1\n :b nil\n}");
+		assert_eq!(s.set("c", "3"), "{:a 1\n :b {:x 1\n     :y 2}\n :c 3\n}");
+		assert_eq!(s.unset("b").unwrap(), "{:a 1\n}");
+	}
+
+	#[test]
+	fn only_key() {
+		assert_eq!(source("{:a 1}").unset("a").unwrap(), "{}");
+		assert_eq!(source("{\n  :a 1\n}").unset("a").unwrap(), "{\n}");
+		assert_eq!(source("{}").set("a", "1"), "{:a 1}");
+		assert!(source("{:a 1}").unset("b").is_none());
+	}
+
+	#[test]
+	fn values() {
+		assert_eq!(value("./world").unwrap(), "\"./world\"");
+		assert_eq!(value("sudo -E").unwrap(), "\"sudo -E\"");
+		assert_eq!(value("30").unwrap(), "30");
+		assert_eq!(value(":string").unwrap(), ":string");
+		assert_eq!(value("[\"sudo\" \"-E\"]").unwrap(), "[\"sudo\" \"-E\"]");
+		assert!(value("[a b]").is_err());
+	}
+
+	#[test]
+	fn refusals() {
+		assert!(refused("@{:a 1}").contains("Not just"));
+		assert!(refused("{:a ;[1 2]}").contains("computed"));
+		assert!(refused("{:a 1 :a 2}").contains("twice"));
+	}
+}
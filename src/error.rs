@@ -1,6 +1,6 @@
 #[macro_export]
 macro_rules! err {
-	($($ident:tt)*) => {{
+	(code: $code:expr; $($ident:tt)*) => {{
 		use colored::Colorize;
 		eprintln!("{} {}", "ERR:".bold().red(), format!($($ident)*).red());
 
@@ -8,8 +8,9 @@ macro_rules! err {
 		panic!("{}", std::backtrace::Backtrace::force_capture());
 
 		#[cfg(not(debug_assertions))]
-		std::process::exit(1)
+		std::process::exit($code)
 	}};
+	($($ident:tt)*) => { $crate::err!(code: 1; $($ident)*) };
 }
 
 #[macro_export]
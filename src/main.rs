@@ -1,7 +1,7 @@
 use std::cell::LazyCell;
 use std::ops::Deref;
 
-use janetrs::{Janet, JanetKeyword, TaggedJanet};
+use janetrs::{Janet, TaggedJanet};
 use colored::Colorize;
 
 mod config;
@@ -11,21 +11,35 @@ mod prelude;
 mod backend;
 mod world;
 mod util;
+mod cache;
+mod strata;
+mod ui;
+mod complete;
+mod edit;
 
 use world::World;
 use util::JanetInto;
-use backend::{Package, PackageInfo};
+use backend::{BackendError, ErrorKind, Package, PackageInfo};
 
-// TODO: make absolute :)
-const DEFAULT_CONF_PATH: &str = "./config.janet";
+// a backend's result, kept from the gc for as long as it's held
+struct Rooted {
+	value: Janet,
+	_root: janetrs::JanetGcRootGuard,
+}
+
+impl Deref for Rooted {
+	type Target = Janet;
+	fn deref(&self) -> &Janet { &self.value }
+}
 
 pub struct PmmExec {
-	rt:       janetrs::client::JanetClient,
 	args:     args::Args,
 	config:   config::Config,
 	backends: backend::Backends,
 	term_col: Option<usize>,
 	world:    LazyCell<World, Box<dyn FnOnce() -> World>>, // fucking closures
+	failed:   std::cell::Cell<i32>, // exit code of the worst backend error so far
+	rt:       janetrs::client::JanetClient, // last, `config` unroots into it when dropped
 }
 
 impl PmmExec {
@@ -37,65 +51,213 @@ impl PmmExec {
 		prelude::append(&mut rt);
 
 		// TODO: maybe have config set a var?
-		let config = config::Config::eval_from_file(&mut rt, 
-			&std::env::var("PMM_CONFIG")
-				.unwrap_or_else(|_| String::from(DEFAULT_CONF_PATH)));
+		let config = config::Config::eval_from_file(&mut rt, &config::find(args.get_with_opt("config")));
+
+		if args.get("offline") {
+			cache::set_offline();
+		}
+
+		let _ = cache::CACHE.set(cache::Cache::new(
+			config.cache_dir.as_ref().map(std::path::PathBuf::from),
+			args.get("refresh"),
+			config.cache_ttl.map(|t| t as u64),
+			config.backends.iter()
+				.filter_map(|(b, c)| c.cache_ttl.map(|t| (b.clone(), t as u64)))
+				.collect()));
+
+		// keyed by backend, "" for everyone else
+		let _ = prelude::exec::ENV.set(config.backends.keys().map(String::as_str).chain([""])
+			.map(|b| (b.to_string(), config.env(b)))
+			.collect());
+		let _ = prelude::exec::ESCALATE.set(config.escalate.clone().unwrap_or_default());
+		prelude::exec::DRY.store(args.get("dry"), std::sync::atomic::Ordering::Relaxed);
+
+		if let Some(r) = &config.bedrock_root {
+			strata::set_root(r);
+		}
 
 		let world_path = config.world_path.clone();
+		let backends = backend::Backends::from_dir(&mut rt, &config);
+		config.check_priority(&backends);
+		let _ = prelude::exec::COLOURS.set(backends.iter().map(|b| (b.name.clone(), b.colour)).collect());
+
+		complete::remember(&world_path, &backends);
+
+		backends.iter().filter_map(|b| b.stratum.as_deref().map(|s| (b, s)))
+			.filter(|(_, s)| !strata::exists(s))
+			.for_each(|(b, s)| warn!("{}: stratum `{s}` not found in {}", b.name, strata::dir(s).display()));
+
 		Self { 
-			backends: backend::Backends::from_dir(&mut rt, &config.backend_dir),
+			backends,
 			world:    LazyCell::new(Box::new(move || World::new(&world_path))),
 			// TODO: have an arg for this mayhps??
 			term_col: term_size::dimensions().map(|(w, _)| w),
+			failed:   Default::default(),
 			config, rt, args,
 		}
 	}
 
 	fn call(&mut self, bname: &str, name: &str, args: impl AsRef<[Janet]>) -> Janet {
-		self.rt.env().unwrap().resolve(format!("{}/{name}", bname)).map_or_else(
+		let f = self.rt.env().unwrap().resolve(format!("{}/{name}", bname)).map_or_else(
 			|| err!("{}: `{name}` not found", bname),
 			|f| match f.unwrap() {
-				TaggedJanet::Function(mut f) => f.call(&args).unwrap_or_else(|e| err!("{e}")),
+				TaggedJanet::Function(_) => f,
 				t => err!("{}: expected `function`, got `{}`", bname, t.kind()),
 			},
-		)
+		);
+
+		let args = args.as_ref().iter().copied().collect::<janetrs::JanetTuple>();
+		match self.call_fn("pmm/try").call([Janet::from(bname), f, Janet::from(args)]).unwrap_or_else(|e| err!("{e}")).janet_into() {
+			Ok(v)  => v,
+			Err(e) => { self.trace(&e); err!(code: e.kind.code(); "{bname}: {e}") },
+		}
+	}
+
+	// print what a backend error carries, with `--verbose`
+	fn trace(&self, e: &BackendError) {
+		if !self.args.get("verbose") { return; }
+
+		e.data.iter().for_each(|d| eprintln!("    {} {d}", "data:".bold()));
+		e.trace.iter().flat_map(|t| t.lines()).for_each(|l| eprintln!("    {}", l.dimmed()));
 	}
 
-	fn call_all_threaded(&mut self, name: &str, args: impl AsRef<[Janet]>) -> Vec<(String, Janet)> {
-		self.rt.add_def(janetrs::env::DefOptions::new("pmm-chan", 
-			self.rt.run(format!("(ev/thread-chan {})", self.backends.len()))
-				.unwrap_or_else(|e| err!("{e}"))));
-
-		self.backends.iter().for_each(|b| {
-			let ns = &b.name;
-			match self.rt.run(format!(
-			"(fn [& x] (ev/spawn-thread (ev/give pmm-chan [\"{ns}\" 
-				(try (apply {ns}/{name} x) ([err _] [:error err]))])))"))
-				.unwrap_or_else(|e| err!("{ns}: {e}")).unwrap() {
-				TaggedJanet::Function(mut f) => f.call(&args).unwrap_or_else(|e| err!("{ns}: {e}")),
-				t => err!("{ns}/{name}: expected `function`, got `{}`", t.kind()),
+	// call the config's `:{name}` function if it has one, errors in it are the config's
+	fn hook(&self, name: &str, args: &[Janet]) -> Option<Janet> {
+		let f = self.config.hook(name)?;
+		let args = args.iter().copied().collect::<janetrs::JanetTuple>();
+
+		match self.call_fn("pmm/try").call([Janet::from("config"), f, Janet::from(args)]).unwrap_or_else(|e| err!("{e}")).janet_into() {
+			Ok(v)  => Some(v),
+			Err(e) => { self.trace(&e); err!(code: e.kind.code(); "config: `:{name}`: {e}") },
+		}
+	}
+
+	// `:filter`, whether to show `pkg` at all
+	fn keep(&self, b: &str, pkg: &Package) -> bool {
+		// not `Janet::is_truthy`, that has it backwards
+		self.hook("filter", &[pkg.into(), Janet::from(b)])
+			.is_none_or(|v| !matches!(v.unwrap(), TaggedJanet::Nil | TaggedJanet::Boolean(false)))
+	}
+
+	fn report(&self, b: &str, e: &BackendError) {
+		warn!("{}: {e}", self.backends.get(b).qualified());
+		self.trace(e);
+		self.failed.set(self.failed.get().max(e.kind.code()));
+	}
+
+	fn call_threaded(&self, backends: &[String], name: &str, args: impl AsRef<[Janet]>) -> Vec<(String, Result<Rooted, BackendError>)> {
+		let mut res = Vec::with_capacity(backends.len());
+		self.call_threaded_each(backends, name, args, |r, _| res.extend(r));
+		res
+	}
+
+	// calls `each` as results arrive, and with `None` once everything's been spawned,
+	// along with the backends still being waited on.
+	// a backend that times out is given up on, not stopped: janet can't kill a thread, so it runs
+	// on until it's done or pmm exits, and whatever it sends back then is dropped
+	fn call_threaded_each(&self, backends: &[String], name: &str, args: impl AsRef<[Janet]>, 
+		mut each: impl FnMut(Option<(String, Result<Rooted, BackendError>)>, &[&str])) {
+		use std::sync::atomic::{AtomicU64, Ordering};
+		use std::time::{Duration, Instant};
+
+		// results of earlier calls that timed out may still trickle in, ignore those
+		static CALL_ID: AtomicU64 = AtomicU64::new(0);
+		let id = CALL_ID.fetch_add(1, Ordering::Relaxed);
+
+		let (tx, rx) = std::sync::mpsc::channel();
+		*prelude::RESULTS.lock().unwrap() = Some(tx);
+
+		let env = self.rt.env().unwrap();
+		let mut spawn = self.call_fn("pmm/spawn");
+		let args = args.as_ref().iter().copied().collect::<janetrs::JanetTuple>();
+
+		let start = Instant::now();
+		let mut pending = backends.iter().filter_map(|ns| match env.resolve(format!("{ns}/{name}")) {
+			Some(f) if matches!(f.unwrap(), TaggedJanet::Function(_)) => {
+				spawn.call([Janet::from(id), Janet::from(ns.as_str()), f, Janet::from(args.clone())])
+					.unwrap_or_else(|e| err!("{ns}: {e}"));
+				// 0 waits as long as it takes, as does anything too long to be a deadline
+				let deadline = self.config.timeout(ns).filter(|t| *t > 0.0).and_then(|t| Duration::try_from_secs_f64(t).ok()
+					.and_then(|d| start.checked_add(d))
+					.map(|d| (t, d)));
+				Some((ns.clone(), deadline))
+			},
+			Some(f) => { each(Some((ns.clone(), Err(BackendError::new(ErrorKind::Backend, format!("expected `function`, got `{}`", f.kind()))))), &[]); None },
+			None    => { each(Some((ns.clone(), Err(BackendError::new(ErrorKind::Backend, format!("`{name}` not found"))))), &[]); None },
+		}).collect::<Vec<(String, _)>>();
+
+		fn names<T>(p: &[(String, T)]) -> Vec<&str> { p.iter().map(|(b, _)| b.as_str()).collect() }
+		each(None, &names(&pending));
+
+		let mut unmarshal = self.call_fn("pmm/unmarshal");
+		while !pending.is_empty() {
+			let recv = match pending.iter().filter_map(|(_, t)| t.map(|(_, d)| d)).min() {
+				Some(d) => rx.recv_timeout(d.saturating_duration_since(Instant::now())),
+				None    => rx.recv().map_err(|_| std::sync::mpsc::RecvTimeoutError::Disconnected),
+			};
+
+			match recv {
+				Ok((i, ns, buf)) if i == id => {
+					pending.retain(|(b, _)| *b != ns);
+
+					let v = unmarshal.call([Janet::from(janetrs::JanetBuffer::from(buf.as_slice()))])
+						.unwrap_or_else(|e| err!("{ns}: {e}"));
+					// `[:ok v]` stays rooted for as long as `v` is around
+					let root = janetrs::JanetGc::obtain().root(v);
+					let res: Result<Janet, BackendError> = v.janet_into();
+					each(Some((ns, res.map(|value| Rooted { value, _root: root }))), &names(&pending));
+				},
+				Ok(_) => {},
+				Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+					let now = Instant::now();
+					let (late, rest) = pending.into_iter()
+						.partition::<Vec<_>, _>(|(_, t)| t.is_some_and(|(_, d)| d <= now));
+					pending = rest;
+
+					late.into_iter().for_each(|(b, t)| each(Some((b, 
+						Err(BackendError::new(ErrorKind::Timeout, format!("timed out after {}s", t.unwrap().0))))), &names(&pending)));
+				},
+				Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => unreachable!(),
 			}
-		});
+		}
 
-		// FIXME: 🤮
-		match self.rt.run(format!("(seq [_ :range [0 {}]] (ev/take pmm-chan))", self.backends.len()))
-			.unwrap_or_else(|e| err!("{e}"))
-			.unwrap() {
-			TaggedJanet::Array(a) => a.into_iter().map(|e| {
-				let TaggedJanet::Tuple(t) = e.unwrap() else { unreachable!() };
-				let mut t = t.into_iter();
-				(t.next().unwrap().to_string(), t.next().unwrap())
-			}).filter(|(b, e)| match e.unwrap() {
-				TaggedJanet::Tuple(t) 
-					if t.len() == 2 && t[0] == Janet::keyword(JanetKeyword::new("error")) 
-					=> { warn!("{b}: {}", t[1]); false },
-				_ => true,
-			}).collect::<Vec<_>>(),
-			t => err!("Expected `array`, got `{}`", t.kind()),
+		*prelude::RESULTS.lock().unwrap() = None;
+	}
+
+	fn call_fn(&self, name: &str) -> janetrs::JanetFunction<'static> {
+		match self.rt.env().unwrap().resolve(name).map(|f| f.unwrap()) {
+			Some(TaggedJanet::Function(f)) => f,
+			_ => err!("`{name}` not found"),
 		}
 	}
 
-	fn sort_by_priority<T>(&self, v: &mut Vec<(String, T)>) {
+	// backends selected with `--backends=a,b`, or all of them
+	fn selected(&self) -> Vec<String> {
+		self.args.get_with_opt("backends").map_or_else(
+			|| self.backends.iter().map(|b| b.name.clone()).collect(),
+			|s| s.split(',').map(|b| self.backends.get(b.trim()).name.clone()).collect())
+	}
+
+	// `cargo/ripgrep` only asks `cargo`, the way packages are shown
+	fn scoped<'a>(&self, arg: &'a str) -> (Vec<String>, &'a str) {
+		arg.split_once('/')
+			.filter(|(b, _)| self.backends.iter().any(|x| x.name == *b || x.qualified() == *b))
+			.map_or_else(|| (self.selected(), arg), |(b, p)| (vec![self.backends.get(b).name.clone()], p))
+	}
+
+	fn call_all_threaded(&self, name: &str, args: impl AsRef<[Janet]>) -> Vec<(String, Rooted)> {
+		self.call_some_threaded(&self.selected(), name, args)
+	}
+
+	fn call_some_threaded(&self, backends: &[String], name: &str, args: impl AsRef<[Janet]>) -> Vec<(String, Rooted)> {
+		self.call_threaded(backends, name, args).into_iter()
+			.filter_map(|(b, r)| match r {
+				Ok(v)  => Some((b, v)),
+				Err(e) => { self.report(&b, &e); None },
+			}).collect()
+	}
+
+	fn sort_by_priority<T>(&self, v: &mut [(String, T)]) {
 		v.sort_unstable_by_key(|(k, _)| self.config.priority.iter()
 			.position(|p| p == k).unwrap_or(usize::MAX))
 	}
@@ -108,9 +270,9 @@ impl PmmExec {
 				.map(|a| format!("{} {} ", "as".bold().cyan(), a.bold()))
 				.unwrap_or_default(),
 			pkg.version.to_string().green().bold(),
-			self.world.deref().has_package(&pkg, &b)
-				.then(|| format!("{} ", "✓".bright_blue().bold()))
-				.unwrap_or_default()),
+			if self.world.deref().has_package(pkg, b) {
+				format!("{} ", "✓".bright_blue().bold())
+			} else { String::new() }),
 		match self.term_col {
 			Some(max) => {
 				let (mut l, r) = pkg.desc.split_whitespace()
@@ -138,40 +300,78 @@ impl PmmExec {
 		match act {
 			Action::Search(args) => {
 				let args = args.iter().map(|p| Janet::from(&**p)).collect::<Vec<_>>();
-				let mut res = self.call_all_threaded("search", &args);
-				self.sort_by_priority(&mut res);
 
-				res.into_iter().for_each(|(b, v)| {
+				let print = |b: &str, v: Janet| {
 					let a = match v.unwrap() {
 						TaggedJanet::Array(a) => a,
 						t => err!("{b}: expected `array`, got `{}`", t.kind()),
 					};
 
-					let b = self.backends.get(&b);
+					let b = self.backends.get(b);
 
 					a.into_iter().for_each(|e| {
 						let pkg: Package = e.janet_into();
+						if !self.keep(&b.name, &pkg) { return }
+
 						let (header, desc) = self.display_pkg(b, &pkg);
 						println!("{header}");
 						desc.into_iter().for_each(|l| println!("{l}"));
-					})
-				})
+					});
+
+					if let Some(age) = cache::stale(&b.name) {
+						println!("{}", format!("({b} results are cached, {} old)", cache::fmt_age(age)).dimmed());
+					}
+				};
+
+				if self.args.get("sorted") {
+					let mut res = self.call_all_threaded("search", &args);
+					self.sort_by_priority(&mut res);
+					return res.into_iter().for_each(|(b, v)| print(&b, *v));
+				}
+
+				// show what we're still waiting on, if anyone's looking
+				let status = std::io::IsTerminal::is_terminal(&std::io::stderr());
+
+				self.call_threaded_each(&self.selected(), "search", &args, |r, pending| {
+					if status { eprint!("\r\x1b[K"); }
+
+					match r {
+						Some((b, Ok(v)))  => print(&b, *v),
+						Some((b, Err(e))) => self.report(&b, &e),
+						None => {},
+					}
+
+					if status && !pending.is_empty() {
+						eprint!("{} {}", "waiting on".bold(), pending.iter()
+							.map(|b| self.backends.get(b).qualified())
+							.collect::<Vec<_>>().join(", "));
+					}
+				});
 			},
 			Action::Info(arg) => {
-				self.call_all_threaded("info", &[Janet::wrap(&*arg)])
+				let (backends, arg) = self.scoped(arg);
+				self.call_some_threaded(&backends, "info", [Janet::wrap(arg)])
 					.into_iter().for_each(|(b, o)| {
 						let b = self.backends.get(&b);
 						let p: PackageInfo = o.janet_into();
 
 						println!("backend:      {b}");
+						if let Some(age) = cache::stale(&b.name) {
+							println!("stale:        cached {} ago", cache::fmt_age(age));
+						}
 						println!("installed:    {}", 
-							if self.world.deref().has_package(&p.pkg, &b)
+							if self.world.deref().has_package(&p.pkg, b)
 								{ "yes" } else { "no" });
 						println!("{p}");
 					});
 			},
 			Action::Add(arg) => {
-				let mut res = self.call_all_threaded("search", &[Janet::wrap(arg)]);
+				if cache::is_offline() {
+					err!("`add` needs the network, not available offline");
+				}
+
+				let (backends, arg) = self.scoped(arg);
+				let mut res = self.call_some_threaded(&backends, "search", [Janet::wrap(arg)]);
 				self.sort_by_priority(&mut res);
 
 				let mut res = res.into_iter().filter_map(|(b, v)| {
@@ -182,7 +382,7 @@ impl PmmExec {
 
 					a.iter().find_map(|p| { 
 						let p: Package = p.janet_into(); 
-						(p.name == arg).then_some(p)
+						(p.name == arg && self.keep(&b, &p)).then_some(p)
 					}).map(|p| (b, p))
 				}).rev().enumerate().collect::<Vec<_>>();
 				
@@ -190,13 +390,36 @@ impl PmmExec {
 					err!("Package `{arg}` not found");
 				}
 
-				let (backend, pkg) = match res.len() {
-					1 => res.pop().map(|(_, x)| x).unwrap(),
+				// found out halfway through the search
+				if cache::is_offline() {
+					err!("`add` needs the network, which is unreachable");
+				}
+
+				// `:pick` gets `{:backend :package}`s in the order they'd be listed, and picks one or leaves it to us
+				let picked = (res.len() > 1).then(|| self.hook("pick", &[res.iter()
+					.map(|(_, (b, p))| Janet::from(janetrs::structs! {
+						keyword![backend] => b.as_str(),
+						keyword![package] => Into::<Janet>::into(p),
+					}))
+					.collect::<janetrs::JanetTuple>().into()])).flatten().filter(|v| !v.is_nil());
+
+				let (backend, pkg) = match (res.len(), picked) {
+					(1, _) => res.pop().map(|(_, x)| x).unwrap(),
+					(_, Some(v)) => {
+						let b = match v.unwrap() {
+							TaggedJanet::Struct(s) => s.get(janetrs::JanetKeyword::new("backend")).map(|b| b.to_string()),
+							_ => None,
+						}.unwrap_or_else(|| err!("config: `:pick`: expected one of the candidates, got `{v}`"));
+
+						res.iter_mut().find(|(_, (c, _))| *c == b).map_or_else(
+							|| err!("config: `:pick`: `{b}` isn't one of the candidates"),
+							|(_, x)| std::mem::take(x))
+					},
 					_ => {
 						res.iter().for_each(|(i, (b, pkg))| {
-							let b = self.backends.get(&b);
+							let b = self.backends.get(b);
 
-							let (header, desc) = self.display_pkg(b, &pkg);
+							let (header, desc) = self.display_pkg(b, pkg);
 							
 							let num = (i + 1).to_string();
 							println!("{}{} {header}", " ".repeat(3 - num.len()), num.purple());
@@ -215,12 +438,20 @@ impl PmmExec {
 					}
 				};
 
+				// `false` from `:before-add` calls it off, raising an error says why
+				let hook = [(&pkg).into(), Janet::from(backend.as_str())];
+				if self.hook("before-add", &hook).is_some_and(|v| v == Janet::boolean(false)) {
+					err!("config: `:before-add` refused `{}` from {}", pkg.name, self.backends.get(&backend));
+				}
+
 				// TODO: document
 				match self.args.get("dry") {
 					true => self.world.add_package(pkg, &backend),
-					false => self.call(&backend, "add", &[Janet::wrap(arg)]), // this should manage world changes
+					false => { self.call(&backend, "add", [Janet::wrap(arg)]); }, // this should manage world changes
 				}
 
+				self.hook("after-add", &hook);
+
 				// does the add func manage world? if yes then that needs to be exposed in the prelude.
 				// whiiich would require making PmmExec static, move .call() (or i guess separate funcs for each)
 				// into Backend, and then have the funcs stored in Backend as JanetFunction :L
@@ -229,44 +460,244 @@ impl PmmExec {
 				// efficient way of converting between janet and rust values
 			},
 
-			Action::Test =>
-				self.call_all_threaded("test", &[])
-					.into_iter().for_each(|(b, o)| println!("{b}: {o:?}")),
+			Action::Del(pkgs) => pkgs.iter().for_each(|p| {
+				let entry = self.world.get(p)
+					.unwrap_or_else(|| err!("Package `{p}` is not in the world file"));
+				let backend = entry.backend.clone();
+
+				// all the world file knows about it
+				let pkg = Janet::from(janetrs::structs! {
+					keyword![name]    => p.as_str(),
+					keyword![version] => entry.version.to_string().as_str(),
+					keyword![alias]   => entry.alias.as_ref().map_or_else(Janet::nil, |a| a.as_str().into()),
+				});
+				if self.hook("before-del", &[pkg, Janet::from(backend.as_str())]).is_some_and(|v| v == Janet::boolean(false)) {
+					err!("config: `:before-del` refused `{p}` from {}", self.backends.get(&backend));
+				}
+
+				match self.args.get("dry") {
+					true => self.world.remove_package(p),
+					false => { self.call(&backend, "del", [Janet::from(p.as_str())]); },
+				}
+			}),
+
+			Action::Backends => {
+				let mut backends = self.selected().into_iter()
+					.map(|b| (b.clone(), self.backends.get(&b)))
+					.collect::<Vec<_>>();
+				self.sort_by_priority(&mut backends);
+
+				backends.into_iter().for_each(|(_, b)| {
+					println!("{b} {}", self.config.priority.iter()
+						.position(|p| *p == b.name)
+						.map_or_else(|| String::from("(no priority)"), |i| format!("(priority {})", i + 1))
+						.purple());
+					println!("    path:          {}", b.path.display());
+					if let Some(s) = &b.stratum {
+						println!("    stratum:       {s}");
+					}
+					println!("    colour:        {} {} {}", b.colour.0, b.colour.1, b.colour.2);
+					println!("    capabilities:  {}", b.caps.join(", "));
+				})
+			},
+
+			Action::BackendsCheck => {
+				let (with, without): (Vec<_>, Vec<_>) = self.selected().into_iter()
+					.partition(|b| self.backends.get(b).exports("health"));
+
+				let mut res = self.call_threaded(&with, "health", []);
+				self.sort_by_priority(&mut res);
+
+				let failed = res.into_iter().fold(false, |failed, (b, r)| {
+					let b = self.backends.get(&b);
+					match r {
+						Ok(v) if *v == Janet::boolean(false) => { 
+							println!("{b}: {}", "failed".red().bold()); true 
+						},
+						Ok(v) => {
+							println!("{b}: {} {}", "ok".green().bold(), 
+								matches!(v.unwrap(), TaggedJanet::String(_))
+									.then(|| v.to_string()).unwrap_or_default());
+							failed
+						},
+						Err(e) => { println!("{b}: {} {e}", "failed".red().bold()); self.trace(&e); true },
+					}
+				});
 
-			_ => todo!(),
+				without.iter().for_each(|b| 
+					println!("{}: {} no `health` function", self.backends.get(b), "skipped".yellow().bold()));
+
+				if failed {
+					std::process::exit(1);
+				}
+			},
+
+			Action::CacheClean => {
+				let cache = cache::CACHE.get().unwrap();
+				let n = cache.clean().unwrap_or_else(|e| err!("{}: {e}", cache.dir.display()));
+				println!("removed {n} cached responses from {}", cache.dir.display());
+			},
+
+			Action::Test =>
+				self.call_all_threaded("test", [])
+					.into_iter().for_each(|(b, o)| println!("{b}: {:?}", *o)),
 		}
 	}
 }
 
+// `pmm config show`
+fn config_cmd(args: &args::Args, path: &[&str], words: &[String]) {
+	let file = config::find(args.get_with_opt("config"));
+
+	let mut rt = janetrs::client::JanetClient::init()
+		.unwrap_or_else(|e| err!("{e}"))
+		.load_env_default();
+	prelude::append(&mut rt);
+
+	match path {
+		["show"] => {
+			let config = Janet::from(config::eval(&mut rt, &file));
+			let Ok(TaggedJanet::Function(mut pretty)) = rt.run("(fn [c] (string/format \"%m\" c))").map(|f| f.unwrap())
+				else { unreachable!() };
+
+			println!("{}", format!("# {}", file.display()).dimmed());
+			println!("{}", pretty.call([config]).unwrap_or_else(|e| err!("{e}")));
+		},
+		// strings as they are for scripts, anything else as `set` would take it
+		["get"] => {
+			let key = config::key(&words[0]);
+			let v = config::eval(&mut rt, &file).get(janetrs::JanetKeyword::new(key)).copied()
+				.unwrap_or_else(|| err!("`:{key}` isn't set"));
+			let Ok(TaggedJanet::Function(mut jdn)) = rt.run("(fn [v] (def v (postwalk |(if (tuple? $) (tuple/brackets ;$) $) v))
+				(try (string/format \"%j\" v) ([_] (string/format \"%q\" v))))").map(|f| f.unwrap())
+				else { unreachable!() };
+
+			match v.unwrap() {
+				TaggedJanet::String(s) => println!("{s}"),
+				_ => println!("{}", jdn.call([v]).unwrap_or_else(|e| err!("{e}"))),
+			}
+		},
+		["set"] => {
+			let key = config::key(&words[0]);
+			let value = edit::value(&words[1]).unwrap_or_else(|e| err!("{e}"));
+			config::save(&mut rt, &file, &edit::Source::read(&file).set(key, &value));
+		},
+		["unset"] => {
+			let key = config::key(&words[0]);
+			let text = edit::Source::read(&file).unset(key)
+				.unwrap_or_else(|| err!("{}: `:{key}` isn't set there", file.display()));
+			config::save(&mut rt, &file, &text);
+		},
+		p => unreachable!("{p:?}"),
+	}
+}
+
+// `pmm init`, writes a config with whatever `:discover` finds
+fn init(args: &args::Args) {
+	let path = config::target(args.get_with_opt("config"));
+	if path.exists() {
+		err!("{}: already exists", path.display());
+	}
+
+	// the config's own directory is somewhere else entirely
+	let backend_dir = args.get_with_opt("backend-dir").unwrap_or("./backends");
+	let backend_dir = std::path::absolute(backend_dir).unwrap_or_else(|e| err!("{backend_dir}: {e}"));
+	let backend_dir = backend_dir.to_string_lossy();
+	let root = args.get_with_opt("bedrock-root").unwrap_or("/bedrock");
+	strata::set_root(root);
+
+	let mut rt = janetrs::client::JanetClient::init()
+		.unwrap_or_else(|e| err!("{e}"))
+		.load_env_default();
+	prelude::append(&mut rt);
+
+	let found = backend::Backends::discover(&mut rt, &backend_dir);
+	if found.is_empty() {
+		err!("{backend_dir}: No backends for any installed package manager");
+	}
+
+	let priority = found.iter().map(|i| format!("{:?}", i.name)).collect::<Vec<_>>().join(" ");
+	path.parent().map_or(Ok(()), std::fs::create_dir_all)
+		.and_then(|_| std::fs::write(&path, format!(
+"{{ :backend-dir {backend_dir:?}
+  :world-path   \"./world\"
+  # every start, load backends once for each stratum with their package manager
+  :bedrock-root {root:?}
+  :discover true
+  :priority [{priority}] }}
+"))).unwrap_or_else(|e| err!("{}: {e}", path.display()));
+
+	found.iter().for_each(|i| println!("found {} {}", i.name.bold(), format!("({}.janet)", i.backend).dimmed()));
+	println!("wrote {}", path.display());
+}
+
 enum Action<'d> {
 	Search(&'d [String]),
 	Info(&'d str),
 	Add(&'d str),
 	Del(&'d [String]),
+	Backends,
+	BackendsCheck,
+	CacheClean,
 	Test // TODO: remove
 }
 
 fn main() {
-	let (args, mut verbs) = args::Args::parse(std::env::args().skip(1));
+	let mut args = args::Args::parse(std::env::args().skip(1));
 	args.handle_base_flags(); // TODO: maybe move into Args::parse
 
+	// there's no config to load yet, or completion can't wait for it
+	if ["init", "config", "completions", "__complete"].iter().any(|c| args.is_command(c)) {
+		let (path, words) = args.command();
+		return match path[0] {
+			"init"        => init(&args),
+			"config"      => config_cmd(&args, &path[1..], &words),
+			"completions" => complete::script(&words[0]),
+			_             => complete::complete(&words),
+		};
+	}
+
 	let mut pmm = PmmExec::init(args);
 
-	let action = match verbs.first().map(|s| s.as_str()) {
-		Some("search") => Action::Search(&verbs[1..]),
+	// the flavours have nothing like these, so they're pmm's whichever is in use
+	let ui = match ["backends", "cache"].iter().any(|c| pmm.args.is_command(c)) {
+		true  => ui::Flavour::Pmm,
+		false => pmm.args.get_with_opt("ui")
+			.map(|u| ui::Flavour::parse(u).unwrap_or_else(|| err!("Unknown UI flavour `{u}`")))
+			.unwrap_or(pmm.config.ui),
+	};
+
+	let (path, short, words) = match ui {
+		ui::Flavour::Pmm => {
+			let (path, words) = pmm.args.command();
+			(path, Vec::new(), words)
+		},
+		_ => {
+			let (short, words) = pmm.args.loose();
+			(Vec::new(), short, words)
+		},
+	};
 
-		Some("info") if verbs.len() > 2 => err!("action `info` expected only one argument"),
-		Some("info") if verbs.len() < 2 => err!("action `info` expected an argument"),
-		Some("info") => Action::Info(&verbs[1]),
+	let action = match path.as_slice() {
+		_ if ui != ui::Flavour::Pmm => ui.action(&short, &words),
 
-		Some("add") if verbs.len() < 2 => err!("action `add` expected at least one argument"),
-		Some("add") => Action::Add(&verbs[1]),
+		["search"] => Action::Search(&words),
+		["info"]   => Action::Info(&words[0]),
+		["add"]    => Action::Add(&words[0]),
+		["del"]    => Action::Del(&words),
 
-		Some("test") => Action::Test,
+		["backends"]          => Action::Backends,
+		["backends", "check"] => Action::BackendsCheck,
+		["cache", "clean"]    => Action::CacheClean,
 
-		Some(a) => err!("Unknown action `{a}`"),
-		None    => err!("No verbs provided"), // TODO: better error message
+		["test"] => Action::Test,
+
+		p => unreachable!("{p:?}"),
 	};
 
 	pmm.cmd(action);
+
+	if pmm.failed.get() != 0 {
+		std::process::exit(pmm.failed.get());
+	}
 }
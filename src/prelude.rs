@@ -1,90 +1,191 @@
-use janetrs::{env::DefOptions, Janet, JanetArray, JanetFunction, JanetKeyword, JanetStruct};
+use std::sync::{mpsc, Mutex};
+
+use janetrs::{Janet, JanetKeyword, TaggedJanet};
 use crate::keyword;
 
-#[janetrs::janet_fn(arity(range(1)))]
-fn rsdbg(args: &mut [Janet]) -> Janet {
-	args.iter()
-		.map(|a| a.unwrap())
-		.for_each(|a| println!("{a:?}"));
-	Janet::nil()
+mod http;
+mod json;
+mod formats;
+mod checksum;
+mod archive;
+pub mod exec;
+
+// call id, backend and marshalled result of a backend thread
+pub type ThreadResult = (u64, String, Vec<u8>);
+
+// backend threads report back through here rather than an `ev/thread-chan`, taking from one
+// of those enters the event loop, which won't return until *every* thread is done
+pub static RESULTS: Mutex<Option<mpsc::Sender<ThreadResult>>> = Mutex::new(None);
+
+// raise an error backends (and `pmm/try`) can tell apart, like `pmm/error` does
+fn raise(kind: &str, msg: impl std::fmt::Display, data: Janet) -> ! {
+	janetrs::util::_panic(Janet::from(janetrs::structs! {
+		keyword![kind]    => Janet::keyword(JanetKeyword::new(kind)),
+		keyword![message] => msg.to_string().as_str(),
+		keyword![data]    => data,
+	}))
 }
 
-#[janetrs::janet_fn(arity(range(1)))]
-fn exec(args: &mut [Janet]) -> Janet {
-	let cmd = std::process::Command::new(args[0].to_string())
-		.args(args.get(1..).unwrap_or_default().iter().map(ToString::to_string).collect::<Vec<_>>())
-		.output().unwrap_or_else(|e| crate::err!("Failed to execute command: {e}"));
-
-	let stdout = str::from_utf8(&cmd.stdout)
-		.unwrap_or_else(|e| crate::err!("{e}"));
-	let stderr = str::from_utf8(&cmd.stderr)
-		.unwrap_or_else(|e| crate::err!("{e}"));
-
-	Janet::from(janetrs::structs! {
-		keyword![status] => cmd.status.code().unwrap_or_default(),
-		keyword![stdout] => stdout,
-		keyword![stderr] => stderr,
-	})
+// key/value pairs of a struct or table
+fn pairs(v: Janet) -> Vec<(Janet, Janet)> {
+	match v.unwrap() {
+		TaggedJanet::Struct(s) => s.into_iter().collect(),
+		TaggedJanet::Table(t)  => t.iter().map(|(k, v)| (*k, *v)).collect(),
+		TaggedJanet::Nil       => Vec::new(),
+		t => janetrs::jpanic!("expected `struct` or `table`, got `{}`", t.kind()),
+	}
 }
 
-#[janetrs::janet_fn(arity(range(1)))]
-fn get_req(args: &mut [Janet]) -> Janet {
-	let url = args[0].to_string();
-
-	minreq::get(url)
-		.with_header("User-Agent", "Bedrock Linux pmm")
-		.send().unwrap_or_else(|e| crate::err!("{e}"))
-		.as_str().unwrap_or_else(|e| crate::err!("{e}"))
-		.into()
+// decoder options, keys are `:keyword`s (the default) or `:string`s
+fn string_keys(opts: Option<Janet>) -> bool {
+	opts.map(pairs).unwrap_or_default().into_iter()
+		.fold(false, |_, (k, v)| match k.to_string().trim_start_matches(':') {
+			"keys" => match v.to_string().trim_start_matches(':') {
+				"keyword" => false,
+				"string"  => true,
+				k => janetrs::jpanic!("`:keys`: expected `:keyword` or `:string`, got `{k}`"),
+			},
+			k => janetrs::jpanic!("unknown option `:{k}`"),
+		})
 }
 
-#[janetrs::janet_fn(arity(fix(1)))]
-fn json_to_janet(args: &mut [Janet]) -> Janet {
-	use miniserde::json::{Object, Value, Number};
-
-	let obj = miniserde::json::from_str::<Object>(&args[0].to_string())
-		.unwrap_or_else(|e| crate::err!("{e}"));
-
-	fn parse_number(n: Number) -> Janet {
-		match n {
-			Number::U64(n) => Janet::uint64(n),
-			Number::I64(n) => Janet::int64(n),
-			Number::F64(n) => Janet::number(n),
-		}
+fn key(k: &str, strings: bool) -> Janet {
+	match strings {
+		true  => Janet::from(janetrs::JanetString::new(k)),
+		false => Janet::keyword(JanetKeyword::new(k)),
 	}
+}
 
-	fn parse_val(val: Value) -> Janet {
-		match val {
-			Value::Null      => Janet::nil(),
-			Value::Bool(b)   => Janet::boolean(b),
-			Value::Number(n) => parse_number(n),
-			Value::String(s) => Janet::string(s.into()),
-			Value::Array(a)  => a.into_iter().map(parse_val).collect::<JanetArray>().into(),
-			Value::Object(o) => parse_obj(o),
-		}
-	}
+#[janetrs::janet_fn(arity(range(1)))]
+fn rsdbg(args: &mut [Janet]) -> Janet {
+	args.iter()
+		.map(|a| a.unwrap())
+		.for_each(|a| println!("{a:?}"));
+	Janet::nil()
+}
+
+#[janetrs::janet_fn(arity(fix(3)))]
+fn give(args: &mut [Janet]) -> Janet {
+	let TaggedJanet::Buffer(res) = args[2].unwrap() else { janetrs::bad_slot!(args, 2, "buffer") };
 
-	fn parse_obj(obj: Object) -> Janet {
-		obj.into_iter()
-			.map(|(k, v)| (Janet::keyword(k.into()), parse_val(v)))
-			.collect::<JanetStruct>().into()
+	// no sender means nobody is waiting anymore
+	if let Some(tx) = RESULTS.lock().unwrap().as_ref() {
+		let id = args[0].try_unwrap::<u64>().unwrap_or_else(|e| janetrs::jpanic!("{e}"));
+		let _ = tx.send((id, args[1].to_string(), res.as_bytes().to_vec()));
 	}
 
-	parse_obj(obj)
+	Janet::nil()
 }
 
 pub fn append(rt: &mut janetrs::client::JanetClient) {
 	use janetrs::env::CFunOptions;
 
 	rt.add_c_fn(CFunOptions::new(c"rsdbg", rsdbg_c));
-	rt.add_c_fn(CFunOptions::new(c"exec", exec_c));
-	rt.add_c_fn(CFunOptions::new(c"get-req", get_req_c));
-	rt.add_c_fn(CFunOptions::new(c"json->janet", json_to_janet_c));
+	rt.add_c_fn(CFunOptions::new(c"exec", exec::exec_c));
+	rt.add_c_fn(CFunOptions::new(c"exec-root", exec::exec_root_c));
+	rt.add_c_fn(CFunOptions::new(c"pmm/env", exec::pmm_env_c));
+	rt.add_c_fn(CFunOptions::new(c"pmm/stratum", exec::pmm_stratum_c));
+	rt.add_c_fn(CFunOptions::new(c"pmm/path", exec::pmm_path_c));
+	rt.add_c_fn(CFunOptions::new(c"get-req", http::get_req_c));
+	rt.add_c_fn(CFunOptions::new(c"http/request", http::request_c));
+	rt.add_c_fn(CFunOptions::new(c"http/get-all", http::get_all_c));
+	rt.add_c_fn(CFunOptions::new(c"http/download", http::download_c));
+	rt.add_c_fn(CFunOptions::new(c"checksum/string", checksum::string_c));
+	rt.add_c_fn(CFunOptions::new(c"checksum/file", checksum::file_c));
+	rt.add_c_fn(CFunOptions::new(c"checksum/verify", checksum::verify_c));
+	rt.add_c_fn(CFunOptions::new(c"archive/extract", archive::extract_c));
+	rt.add_c_fn(CFunOptions::new(c"json/decode", json::decode_c));
+	rt.add_c_fn(CFunOptions::new(c"json/encode", json::encode_c));
+	rt.add_c_fn(CFunOptions::new(c"toml/decode", formats::toml_decode_c));
+	rt.add_c_fn(CFunOptions::new(c"ini/decode", formats::ini_decode_c));
+	rt.add_c_fn(CFunOptions::new(c"kv/decode", formats::kv_decode_c));
+	// older name, kept for existing backends
+	rt.add_c_fn(CFunOptions::new(c"json->janet", json::decode_c));
+	rt.add_c_fn(CFunOptions::new(c"pmm/give", give_c));
 
 	rt.run("(defn ffi-func [ctx sym & args]
 				  (let [ptr (ffi/lookup ctx sym)
 						  sig (apply ffi/signature :default args)]
 					  (fn [& args] (apply ffi/call ptr sig args))))").unwrap();
 
+	// `(pmm/error :network "can't reach the index" {:url url})`
+	rt.run("(defn pmm/error [kind msg &opt data]
+				  (error {:kind kind :message msg :data data}))").unwrap();
+
+	// call `f` on behalf of backend `ns`, catching errors as `[:error [kind msg data trace]]`
+	rt.run("(defn pmm/try [ns f args]
+				  (try [:ok (with-dyns [:pmm-backend ns] (f ;args))]
+					  ([err fib]
+						  (def trace @\"\")
+						  (with-dyns [:err trace] (debug/stacktrace fib err nil))
+						  [:error (if (and (dictionary? err) (keyword? (err :kind)))
+							  [(err :kind) (string (err :message))
+								  (if (nil? (err :data)) nil (string/format \"%q\" (err :data)))
+								  (string trace)]
+							  [:backend (string err) nil (string trace)])])))").unwrap();
+
+	rt.run("(defn pmm/spawn [id ns f args]
+				  (ev/spawn-thread
+					  (pmm/give id ns
+						  (let [res (pmm/try ns f args)]
+							  (try (marshal res)
+								  ([err] (marshal [:error [:backend (string \"can't return result: \" err) nil nil]])))))))").unwrap();
+
+	rt.run("(defn pmm/unmarshal [buf] (unmarshal buf))").unwrap();
+
+	// like `import`, but a fresh module each time, loaded on behalf of `ns` with its `PARAMS`.
+	// called straight from rust where there's no `curenv`, so it keeps hold of this one
+	rt.run("(def pmm/load
+				  (let [env (curenv)]
+					  (fn pmm/load [path ns params]
+						  (def module (make-env env))
+						  (put module :pmm-backend ns)
+						  (put module 'PARAMS @{:value params})
+						  (merge-module env (dofile path :env module) (string ns \"/\")))))").unwrap();
+
+	// `DETECT` as written in the backend at `path`, read without running anything in it
+	rt.run("(defn pmm/detect [path]
+				  (def p (parser/new))
+				  (parser/consume p (slurp path))
+				  (parser/eof p)
+				  (when (= :error (parser/status p)) (error (parser/error p)))
+				  (var found nil)
+				  (while (parser/has-more p)
+					  (def form (parser/produce p))
+					  (when (and (tuple? form) (= :parens (tuple/type form))
+								 (index-of (first form) ['def 'def-]) (= 'DETECT (get form 1)))
+						  (set found (last form))))
+				  (cond
+					  (or (nil? found) (string? found)) found
+					  (and (tuple? found) (= :brackets (tuple/type found)) (all string? found)) found
+					  (error \"`DETECT`: Expected a `string` or `[...]` of them, written out\")))").unwrap();
+
+	// the config at `path` over the ones it `:include`s, maps one level deep are merged.
+	// includes and relative `:backend-dir`/`:world-path` are relative to the file they're in
+	rt.run("(defn pmm/config [path &opt stack]
+			  (default stack @[])
+			  (when (index-of path stack) (error (string path \": includes itself\")))
+			  (array/push stack path)
+			  (def dir (string/slice path 0 (inc (last (string/find-all \"/\" path)))))
+			  (defn rel [p] (if (string/has-prefix? \"/\" p) p (string dir p)))
+			  (def conf (eval-string (slurp path)))
+			  (unless (struct? conf) (error (string path \": Expected `map`, got `\" (type conf) \"`\")))
+			  (def out @{})
+			  (defn add [k v] (put out k (if (and (struct? v) (struct? (out k))) (table/to-struct (merge (out k) v)) v)))
+			  (each i (let [i (conf :include)] (if (indexed? i) i (if i [i] [])))
+			    (eachp [k v] (pmm/config (rel i) stack) (add k v)))
+			  (eachp [k v] conf
+			    (add k (if (and (index-of k [:backend-dir :world-path]) (string? v)) (rel v) v)))
+			  (put out :include nil)
+			  (array/pop stack)
+			  (table/to-struct out))").unwrap();
+
+}
 
+// janet state is per thread, so every test gets its own
+#[cfg(test)]
+pub fn test_runtime() -> janetrs::client::JanetClient {
+	let mut rt = janetrs::client::JanetClient::init().unwrap().load_env_default();
+	append(&mut rt);
+	rt
 }
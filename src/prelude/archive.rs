@@ -0,0 +1,336 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use janetrs::{Janet, TaggedJanet};
+use crate::keyword;
+
+#[derive(Clone, Copy)]
+enum Format { Tar, TarGz, TarXz, TarZst, Zip }
+
+impl Format {
+	fn parse(s: &str) -> Option<Self> {
+		match s.trim_start_matches(':') {
+			"tar"             => Some(Self::Tar),
+			"tar.gz" | "tgz"  => Some(Self::TarGz),
+			"tar.xz" | "txz"  => Some(Self::TarXz),
+			"tar.zst" | "tzst" => Some(Self::TarZst),
+			"zip"             => Some(Self::Zip),
+			_ => None,
+		}
+	}
+
+	// by extension, then by magic bytes
+	fn detect(path: &str) -> Option<Self> {
+		let name = path.to_lowercase();
+
+		[".tar", ".tar.gz", ".tgz", ".crate", ".tar.xz", ".txz", ".tar.zst", ".tzst", ".zip", ".whl", ".jar"].iter()
+			.find(|ext| name.ends_with(*ext))
+			.and_then(|ext| match *ext {
+				".crate" => Some(Self::TarGz),
+				".whl" | ".jar" => Some(Self::Zip),
+				ext => Self::parse(&ext[1..]),
+			})
+			.or_else(|| {
+				let mut magic = [0; 6];
+				File::open(path).and_then(|mut f| f.read_exact(&mut magic)).ok()?;
+
+				match magic {
+					[0x1f, 0x8b, ..]                   => Some(Self::TarGz),
+					[0xfd, b'7', b'z', b'X', b'Z', 0]  => Some(Self::TarXz),
+					[0x28, 0xb5, 0x2f, 0xfd, ..]       => Some(Self::TarZst),
+					[b'P', b'K', 3, 4, ..]             => Some(Self::Zip),
+					_ => None,
+				}
+			})
+	}
+}
+
+#[derive(Default)]
+struct Options {
+	strip:  usize,
+	format: Option<Format>,
+}
+
+impl From<Janet> for Options {
+	fn from(opts: Janet) -> Self {
+		super::pairs(opts).into_iter().fold(Self::default(), |mut o, (k, v)| {
+			match k.to_string().trim_start_matches(':') {
+				"strip" => o.strip = match v.unwrap() {
+					TaggedJanet::Number(n) => n as usize,
+					t => janetrs::jpanic!("`:strip`: expected `number`, got `{}`", t.kind()),
+				},
+				"format" => o.format = Some(Format::parse(&v.to_string())
+					.unwrap_or_else(|| janetrs::jpanic!("`:format`: unknown format `{v}`"))),
+				k => janetrs::jpanic!("unknown option `:{k}`"),
+			}; o
+		})
+	}
+}
+
+// `path` relative to the archive root with `strip` leading components dropped.
+// `Ok(None)` if nothing is left, `Err` if it would land outside of it
+fn relative(path: &Path, strip: usize) -> Result<Option<PathBuf>, ()> {
+	let parts = path.components()
+		.filter(|c| *c != Component::CurDir)
+		.map(|c| match c {
+			Component::Normal(p) => Ok(p),
+			_ => Err(()),
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	Ok(parts.get(strip..).filter(|p| !p.is_empty()).map(|p| p.iter().collect()))
+}
+
+// symlinks may only point somewhere else inside `dest`. checked by path, so `..` may only lead:
+// after a name it goes up from wherever that name ends up, and that may be (or later become)
+// another symlink, `s -> .` then `t -> s/..`
+fn link_target(rel: &Path, target: &Path) -> Result<(), ()> {
+	let named_then_up = target.components()
+		.skip_while(|c| matches!(c, Component::ParentDir | Component::CurDir))
+		.any(|c| c == Component::ParentDir);
+
+	if target.is_absolute() || named_then_up {
+		return Err(());
+	}
+
+	rel.parent().unwrap_or(Path::new("")).join(target).components()
+		.try_fold(0usize, |depth, c| match c {
+			Component::ParentDir => depth.checked_sub(1).ok_or(()),
+			Component::Normal(_) => Ok(depth + 1),
+			_ => Ok(depth),
+		})
+		.map(|_| ())
+}
+
+fn is_symlink(path: &Path) -> bool {
+	path.symlink_metadata().is_ok_and(|m| m.is_symlink())
+}
+
+// whether getting to `rel` means going through a symlink
+fn through_symlink(dest: &Path, rel: &Path) -> bool {
+	rel.ancestors().skip(1)
+		.filter(|a| !a.as_os_str().is_empty())
+		.any(|a| is_symlink(&dest.join(a)))
+}
+
+// never write through a symlink, and replace rather than follow one that's already there
+fn prepare(dest: &Path, rel: &Path) -> std::io::Result<PathBuf> {
+	if through_symlink(dest, rel) {
+		return Err(std::io::Error::other("parent directory is a symlink"));
+	}
+
+	let path = dest.join(rel);
+	if is_symlink(&path) {
+		std::fs::remove_file(&path)?;
+	}
+	if let Some(p) = path.parent() {
+		std::fs::create_dir_all(p)?;
+	}
+
+	Ok(path)
+}
+
+fn fail(archive: &str, entry: Option<&Path>, msg: impl std::fmt::Display) -> ! {
+	let data = match entry {
+		Some(e) => Janet::from(janetrs::structs! {
+			keyword![path]  => archive,
+			keyword![entry] => e.to_string_lossy().as_ref(),
+		}),
+		None => Janet::from(janetrs::structs! { keyword![path] => archive }),
+	};
+
+	match entry {
+		Some(e) => super::raise("archive", format!("{archive}: {}: {msg}", e.display()), data),
+		None    => super::raise("archive", format!("{archive}: {msg}"), data),
+	}
+}
+
+fn tar(archive: &str, reader: Box<dyn Read>, dest: &Path, strip: usize) {
+	let mut tar = tar::Archive::new(reader);
+	let entries = tar.entries().unwrap_or_else(|e| fail(archive, None, e));
+
+	entries.for_each(|entry| {
+		let mut entry = entry.unwrap_or_else(|e| fail(archive, None, e));
+		let name = entry.path().unwrap_or_else(|e| fail(archive, None, e)).into_owned();
+
+		let Some(rel) = relative(&name, strip).unwrap_or_else(|_| fail(archive, Some(&name), "outside of the archive"))
+			else { return };
+		let link = entry.link_name().unwrap_or_else(|e| fail(archive, Some(&name), e)).map(|l| l.into_owned());
+		let path = prepare(dest, &rel).unwrap_or_else(|e| fail(archive, Some(&name), e));
+
+		let res = match (entry.header().entry_type(), link) {
+			(tar::EntryType::Symlink, Some(target)) => {
+				link_target(&rel, &target).unwrap_or_else(|_| fail(archive, Some(&name), "links outside of the archive"));
+				std::os::unix::fs::symlink(target, &path)
+			},
+			// hard links name another entry, relative to the archive root. not a symlink or one
+			// under a symlink, a relative symlink would mean something else wherever it's linked to
+			(tar::EntryType::Link, Some(target)) => {
+				let target = relative(&target, strip).ok().flatten()
+					.filter(|t| !through_symlink(dest, t) && !is_symlink(&dest.join(t)))
+					.unwrap_or_else(|| fail(archive, Some(&name), "links outside of the archive"));
+
+				// extracting over an earlier extraction
+				match std::fs::remove_file(&path) {
+					Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+					_ => std::fs::hard_link(dest.join(target), &path),
+				}
+			},
+			(tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::Directory, _) =>
+				entry.unpack(&path).map(|_| ()),
+			// devices, fifos and the like have no business in a package
+			_ => Ok(()),
+		};
+
+		res.unwrap_or_else(|e| fail(archive, Some(&name), e));
+	});
+}
+
+fn zip(archive: &str, file: File, dest: &Path, strip: usize) {
+	use std::os::unix::fs::PermissionsExt;
+
+	let mut zip = zip::ZipArchive::new(file).unwrap_or_else(|e| fail(archive, None, e));
+
+	(0..zip.len()).for_each(|i| {
+		let mut f = zip.by_index(i).unwrap_or_else(|e| fail(archive, None, e));
+		let name = PathBuf::from(f.name());
+
+		let Some(rel) = relative(&name, strip).unwrap_or_else(|_| fail(archive, Some(&name), "outside of the archive"))
+			else { return };
+		let path = prepare(dest, &rel).unwrap_or_else(|e| fail(archive, Some(&name), e));
+
+		let res = match (f.is_dir(), f.is_symlink()) {
+			(true, _) => std::fs::create_dir_all(&path),
+			(_, true) => {
+				let mut target = String::new();
+				f.read_to_string(&mut target).unwrap_or_else(|e| fail(archive, Some(&name), e));
+				link_target(&rel, Path::new(&target)).unwrap_or_else(|_| fail(archive, Some(&name), "links outside of the archive"));
+				std::os::unix::fs::symlink(target, &path)
+			},
+			_ => File::create(&path)
+				.and_then(|mut out| std::io::copy(&mut f, &mut out))
+				.and_then(|_| match f.unix_mode() {
+					Some(m) => std::fs::set_permissions(&path, std::fs::Permissions::from_mode(m & 0o777)),
+					None => Ok(()),
+				}),
+		};
+
+		res.unwrap_or_else(|e| fail(archive, Some(&name), e));
+	});
+}
+
+// `(archive/extract "/tmp/pkg.tar.gz" "/tmp/build" {:strip 1})`, format from the name or contents
+// unless given with `:format`. entries that would end up outside of `dest`, by path or by symlink,
+// fail the whole extraction
+#[janetrs::janet_fn(arity(range(2, 3)))]
+pub fn extract(args: &mut [Janet]) -> Janet {
+	let opts = Options::from(args.get(2).copied().unwrap_or_else(Janet::nil));
+	let (archive, dest) = (args[0].to_string(), args[1].to_string());
+
+	let format = opts.format.or_else(|| Format::detect(&archive))
+		.unwrap_or_else(|| fail(&archive, None, "unknown archive format"));
+	let file = File::open(&archive).unwrap_or_else(|e| fail(&archive, None, e));
+
+	std::fs::create_dir_all(&dest).unwrap_or_else(|e| fail(&archive, None, format!("{dest}: {e}")));
+	let dest_path = Path::new(&dest);
+
+	match format {
+		Format::Tar    => tar(&archive, Box::new(file), dest_path, opts.strip),
+		Format::TarGz  => tar(&archive, Box::new(flate2::read::GzDecoder::new(file)), dest_path, opts.strip),
+		Format::TarXz  => tar(&archive, Box::new(xz2::read::XzDecoder::new(file)), dest_path, opts.strip),
+		Format::TarZst => tar(&archive, Box::new(zstd::Decoder::new(file).unwrap_or_else(|e| fail(&archive, None, e))),
+			dest_path, opts.strip),
+		Format::Zip    => zip(&archive, file, dest_path, opts.strip),
+	}
+
+	Janet::from(dest.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::File;
+	use std::os::unix::fs::MetadataExt;
+	use std::path::Path;
+
+	use tar::EntryType;
+
+	// `(archive/extract ...)`'s error, or `nil`
+	fn extract(archive: &Path, dest: &Path) -> String {
+		crate::prelude::test_runtime()
+			.run(format!("(try (do (archive/extract {:?} {:?}) nil) ([e] (e :message)))", archive, dest))
+			.unwrap().to_string()
+	}
+
+	fn build(path: &Path, files: &[(&str, &str)], links: &[(EntryType, &str, &str)]) {
+		let mut b = tar::Builder::new(File::create(path).unwrap());
+
+		files.iter().for_each(|(name, data)| {
+			let mut h = tar::Header::new_gnu();
+			h.set_size(data.len() as u64);
+			h.set_mode(0o644);
+			b.append_data(&mut h, name, data.as_bytes()).unwrap();
+		});
+		links.iter().for_each(|(kind, name, target)| {
+			let mut h = tar::Header::new_gnu();
+			h.set_entry_type(*kind);
+			h.set_size(0);
+			h.set_mode(0o777);
+			b.append_link(&mut h, name, target).unwrap();
+		});
+
+		b.finish().unwrap();
+	}
+
+	#[test]
+	fn symlink_back_out_through_symlink() {
+		let root = crate::util::test_dir("archive-escape");
+		std::fs::write(root.join("secret"), "secret").unwrap();
+		build(&root.join("a.tar"), &[], &[
+			(EntryType::Symlink, "s", "."),
+			(EntryType::Symlink, "t", "s/.."),
+			(EntryType::Link, "stolen", "t/secret"),
+		]);
+
+		assert!(extract(&root.join("a.tar"), &root.join("dest")).contains("links outside of the archive"));
+		assert!(!root.join("dest/stolen").exists());
+		assert_eq!(root.join("secret").metadata().unwrap().nlink(), 1);
+	}
+
+	#[test]
+	fn hard_link_through_symlink() {
+		let root = crate::util::test_dir("archive-hard-link");
+		build(&root.join("a.tar"), &[("sub/f", "f")], &[
+			(EntryType::Symlink, "s", "sub"),
+			(EntryType::Link, "through", "s/f"),
+		]);
+		assert!(extract(&root.join("a.tar"), &root.join("dest")).contains("links outside of the archive"));
+
+		// `../../f` from `a/b` is `f`, but not from wherever a hard link to it ends up
+		build(&root.join("b.tar"), &[("f", "f")], &[
+			(EntryType::Symlink, "a/b/s", "../../f"),
+			(EntryType::Link, "l", "a/b/s"),
+		]);
+		assert!(extract(&root.join("b.tar"), &root.join("dest2")).contains("links outside of the archive"));
+	}
+
+	#[test]
+	fn links_inside() {
+		let root = crate::util::test_dir("archive-inside");
+		build(&root.join("a.tar"), &[("f", "f"), ("a/g", "g")], &[
+			(EntryType::Symlink, "a/up", "../f"),
+			(EntryType::Symlink, "down", "a/g"),
+			(EntryType::Link, "h", "f"),
+		]);
+
+		let dest = root.join("dest");
+		assert_eq!(extract(&root.join("a.tar"), &dest), "nil");
+		assert_eq!(std::fs::read_to_string(dest.join("a/up")).unwrap(), "f");
+		assert_eq!(std::fs::read_to_string(dest.join("down")).unwrap(), "g");
+		assert_eq!(dest.join("h").metadata().unwrap().ino(), dest.join("f").metadata().unwrap().ino());
+
+		// again, over what's already there
+		assert_eq!(extract(&root.join("a.tar"), &dest), "nil");
+		assert_eq!(dest.join("h").metadata().unwrap().ino(), dest.join("f").metadata().unwrap().ino());
+	}
+}
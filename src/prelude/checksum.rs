@@ -0,0 +1,126 @@
+use std::io::Read;
+
+use janetrs::{Janet, TaggedJanet};
+use sha2::Digest;
+use crate::keyword;
+
+#[derive(Clone, Copy)]
+pub enum Algo { Sha256, Sha512, Blake3 }
+
+impl Algo {
+	pub fn parse(s: &str) -> Option<Self> {
+		match s.trim_start_matches(':') {
+			"sha256" => Some(Self::Sha256),
+			"sha512" => Some(Self::Sha512),
+			"blake3" => Some(Self::Blake3),
+			_ => None,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Sha256 => "sha256",
+			Self::Sha512 => "sha512",
+			Self::Blake3 => "blake3",
+		}
+	}
+}
+
+fn algo(v: Janet) -> Algo {
+	Algo::parse(&v.to_string())
+		.unwrap_or_else(|| janetrs::jpanic!("unknown checksum `{v}`, expected `:sha256`, `:sha512` or `:blake3`"))
+}
+
+pub enum Hasher {
+	Sha256(sha2::Sha256),
+	Sha512(sha2::Sha512),
+	Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+	pub fn new(algo: Algo) -> Self {
+		match algo {
+			Algo::Sha256 => Self::Sha256(sha2::Sha256::new()),
+			Algo::Sha512 => Self::Sha512(sha2::Sha512::new()),
+			Algo::Blake3 => Self::Blake3(Box::default()),
+		}
+	}
+
+	pub fn update(&mut self, data: &[u8]) {
+		match self {
+			Self::Sha256(h) => h.update(data),
+			Self::Sha512(h) => h.update(data),
+			Self::Blake3(h) => { h.update(data); },
+		}
+	}
+
+	// lowercase hex
+	pub fn finish(self) -> String {
+		let bytes = match self {
+			Self::Sha256(h) => h.finalize().to_vec(),
+			Self::Sha512(h) => h.finalize().to_vec(),
+			Self::Blake3(h) => h.finalize().as_bytes().to_vec(),
+		};
+
+		bytes.iter().map(|b| format!("{b:02x}")).collect()
+	}
+}
+
+pub fn hash_file(algo: Algo, path: &str) -> std::io::Result<String> {
+	let mut f = std::fs::File::open(path)?;
+	let mut h = Hasher::new(algo);
+	let mut buf = vec![0; 64 * 1024];
+
+	loop {
+		match f.read(&mut buf)? {
+			0 => return Ok(h.finish()),
+			n => h.update(&buf[..n]),
+		}
+	}
+}
+
+// raises `:checksum` with what was expected and what we got
+pub fn mismatch(algo: Algo, path: &str, expected: &str, actual: &str) -> ! {
+	super::raise("checksum", format!("{path}: {} mismatch", algo.name()), Janet::from(janetrs::structs! {
+		keyword![path]     => path,
+		keyword![expected] => expected,
+		keyword![actual]   => actual,
+	}))
+}
+
+// `(checksum/string :sha256 body)`
+#[janetrs::janet_fn(arity(fix(2)))]
+pub fn string(args: &mut [Janet]) -> Janet {
+	let mut h = Hasher::new(algo(args[0]));
+
+	match args[1].unwrap() {
+		TaggedJanet::String(s) => h.update(s.as_bytes()),
+		TaggedJanet::Buffer(b) => h.update(b.as_bytes()),
+		_ => janetrs::bad_slot!(args, 1, "string|buffer"),
+	}
+
+	Janet::from(h.finish().as_str())
+}
+
+// `(checksum/file :blake3 "/tmp/pkg.tar.zst")`
+#[janetrs::janet_fn(arity(fix(2)))]
+pub fn file(args: &mut [Janet]) -> Janet {
+	let path = args[1].to_string();
+
+	hash_file(algo(args[0]), &path)
+		.map_or_else(|e| super::raise("other", format!("{path}: {e}"), Janet::nil()), |h| Janet::from(h.as_str()))
+}
+
+// `(checksum/verify :sha256 path expected)`, true or raises `:checksum`
+#[janetrs::janet_fn(arity(fix(3)))]
+pub fn verify(args: &mut [Janet]) -> Janet {
+	let (algo, path, expected) = (algo(args[0]), args[1].to_string(), args[2].to_string());
+
+	let actual = hash_file(algo, &path)
+		.unwrap_or_else(|e| super::raise("other", format!("{path}: {e}"), Janet::nil()));
+
+	match actual.eq_ignore_ascii_case(expected.trim()) {
+		true  => Janet::boolean(true),
+		false => mismatch(algo, &path, &expected, &actual),
+	}
+}
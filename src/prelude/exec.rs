@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use janetrs::{Janet, JanetString, TaggedJanet};
+use colored::Colorize;
+use crate::keyword;
+
+// `Config::env` for each backend, every child gets it
+pub static ENV: OnceLock<HashMap<String, HashMap<String, String>>> = OnceLock::new();
+
+fn env(backend: Option<&str>) -> HashMap<String, String> {
+	ENV.get().and_then(|e| e.get(backend.unwrap_or_default()).or_else(|| e.get(""))).cloned().unwrap_or_default()
+}
+
+// `(pmm/env "KEY")`, the config's value or the environment's
+#[janetrs::janet_fn(arity(fix(1)))]
+pub fn pmm_env(args: &mut [Janet]) -> Janet {
+	let key = args[0].to_string();
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+
+	env(backend.as_deref()).get(&key).cloned()
+		.or_else(|| std::env::var(&key).ok())
+		.map_or_else(Janet::nil, |v| Janet::from(v.as_str()))
+}
+
+// `(pmm/stratum)`, the stratum the calling backend is bound to, or nil
+#[janetrs::janet_fn(arity(fix(0)))]
+pub fn pmm_stratum(_args: &mut [Janet]) -> Janet {
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+	crate::strata::of(backend.as_deref()).map_or_else(Janet::nil, Janet::from)
+}
+
+// `(pmm/path "/etc/apt/sources.list")`, as seen from outside of the backend's stratum
+#[janetrs::janet_fn(arity(fix(1)))]
+pub fn pmm_path(args: &mut [Janet]) -> Janet {
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+	let path = args[0].to_string();
+
+	match crate::strata::of(backend.as_deref()) {
+		Some(s) => Janet::from(crate::strata::dir(s).join(path.trim_start_matches('/')).to_string_lossy().as_ref()),
+		None    => Janet::from(path.as_str()),
+	}
+}
+
+// backend colours, for `:stream` prefixes
+pub static COLOURS: OnceLock<HashMap<String, (u8, u8, u8)>> = OnceLock::new();
+
+// `:escalate`, what `exec-root` wraps commands in
+pub static ESCALATE: OnceLock<Vec<String>> = OnceLock::new();
+
+// `--dry`, no escalating
+pub static DRY: AtomicBool = AtomicBool::new(false);
+
+#[derive(Default)]
+pub struct Options {
+	pub cwd:     Option<String>,
+	pub env:     Vec<(String, String)>,
+	pub stdin:   Option<Vec<u8>>,
+	pub stream:  bool,
+	pub timeout: Option<f64>,
+}
+
+impl From<Janet> for Options {
+	fn from(opts: Janet) -> Self {
+		super::pairs(opts).into_iter().fold(Self::default(), |mut o, (k, v)| {
+			match k.to_string().trim_start_matches(':') {
+				"cwd"    => o.cwd = Some(v.to_string()),
+				"env"    => o.env = super::pairs(v).into_iter()
+					.map(|(k, v)| (k.to_string(), v.to_string()))
+					.collect(),
+				"stdin"  => o.stdin = match v.unwrap() {
+					TaggedJanet::String(s) => Some(s.as_bytes().to_vec()),
+					TaggedJanet::Buffer(b) => Some(b.as_bytes().to_vec()),
+					TaggedJanet::Nil => None,
+					t => janetrs::jpanic!("`:stdin`: expected `string` or `buffer`, got `{}`", t.kind()),
+				},
+				"stream" => o.stream = !matches!(v.unwrap(), TaggedJanet::Nil | TaggedJanet::Boolean(false)),
+				// 0 waits as long as it takes, like the config's `:timeout`
+				"timeout" => o.timeout = match v.unwrap() {
+					TaggedJanet::Number(n) if n.is_finite() && n >= 0.0 => (n > 0.0).then_some(n),
+					TaggedJanet::Number(n) => janetrs::jpanic!("`:timeout`: expected seconds, 0 or more, got `{n}`"),
+					t => janetrs::jpanic!("`:timeout`: expected `number`, got `{}`", t.kind()),
+				},
+				k => janetrs::jpanic!("unknown option `:{k}`"),
+			}; o
+		})
+	}
+}
+
+// `cargo | `, in the backend's colour
+pub(super) fn prefix(backend: Option<&str>) -> String {
+	let name = backend.unwrap_or("exec");
+	let (r, g, b) = COLOURS.get().and_then(|c| c.get(name)).copied()
+		.unwrap_or((u8::MAX, u8::MAX, u8::MAX));
+	let name = match crate::strata::of(backend) {
+		Some(s) if !name.starts_with(&format!("{s}:")) => format!("{s}:{name}"),
+		_ => name.to_string(),
+	};
+
+	format!("\x1b[38;2;{r};{g};{b}m{}\x1b[0m {} ", name.bold(), "|".dimmed())
+}
+
+// collect a pipe, passing it through line by line if there's a prefix
+fn pump(pipe: impl std::io::Read + Send + 'static, prefix: Option<String>, stderr: bool) 
+	-> std::thread::JoinHandle<Vec<u8>> {
+	std::thread::spawn(move || {
+		let mut pipe = std::io::BufReader::new(pipe);
+		let mut out = Vec::new();
+		let mut line = Vec::new();
+
+		while pipe.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+			if let Some(p) = &prefix {
+				let mut l = line.clone();
+				if !l.ends_with(b"\n") { l.push(b'\n'); }
+
+				let _ = match stderr {
+					true  => std::io::stderr().lock().write_all(&[p.as_bytes(), &l].concat()),
+					false => std::io::stdout().lock().write_all(&[p.as_bytes(), &l].concat()),
+				};
+			}
+
+			out.append(&mut line);
+		}
+
+		out
+	})
+}
+
+// options, if given, then the command
+fn parse(args: &[Janet]) -> (Options, Vec<String>) {
+	let (opts, args) = match args[0].unwrap() {
+		TaggedJanet::Struct(_) | TaggedJanet::Table(_) => (Options::from(args[0]), &args[1..]),
+		_ => (Options::default(), args),
+	};
+
+	if args.is_empty() {
+		janetrs::jpanic!("exec: no command given");
+	}
+
+	(opts, args.iter().map(ToString::to_string).collect())
+}
+
+// `(exec "cargo" "install" name)`, or with options first:
+// `(exec {:cwd dir :env {"K" "v"} :stdin "..." :stream true :timeout 60} "cargo" "install" name)`
+#[janetrs::janet_fn(arity(range(1)))]
+pub fn exec(args: &mut [Janet]) -> Janet {
+	let (opts, argv) = parse(args);
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+
+	run(opts, crate::strata::command(crate::strata::of(backend.as_deref()), argv))
+}
+
+fn is_root() -> bool {
+	// SAFETY: can't fail, and touches nothing
+	unsafe { libc::geteuid() == 0 }
+}
+
+// whether `tool` goes on without asking again once it's been given credentials.
+// sudo keeps a timestamp, doas only with `persist` and run0 only if polkit says so
+fn keeps_credentials(tool: &[String]) -> Option<bool> {
+	let flag = match tool[0].rsplit('/').next() {
+		Some("sudo") => return Some(true),
+		Some("doas") => "-n",
+		Some("run0") => "--no-ask-password",
+		_ => return None,
+	};
+
+	Command::new(&tool[0]).arg(flag).args(&tool[1..]).arg("true")
+		.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null())
+		.status().ok().map(|s| s.success())
+}
+
+// `exec`, as root through `:escalate`. credentials are asked for once per run where the tool
+// can keep them, otherwise every call asks again and there's a warning saying so
+#[janetrs::janet_fn(arity(range(1)))]
+pub fn exec_root(args: &mut [Janet]) -> Janet {
+	let (opts, argv) = parse(args);
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+	let argv = crate::strata::command(crate::strata::of(backend.as_deref()), argv);
+
+	if DRY.load(Ordering::Relaxed) {
+		super::raise("dry", format!("refusing to run `{}` as root in --dry mode", argv.join(" ")), 
+			Janet::from(janetrs::structs! { keyword![command] => argv.join(" ").as_str() }));
+	}
+
+	if is_root() {
+		return run(opts, argv);
+	}
+
+	let tool = ESCALATE.get()
+		.filter(|t| !t.is_empty())
+		.unwrap_or_else(|| janetrs::jpanic!("exec-root: no `:escalate` tool configured"));
+
+	// sudo can refresh its timestamp by itself, doas (with `persist`) and friends need a command
+	static VALIDATED: OnceLock<Result<(), String>> = OnceLock::new();
+	let validated = VALIDATED.get_or_init(|| {
+		let status = match tool[0].rsplit('/').next() {
+			Some("sudo") => Command::new(&tool[0]).arg("-v").status(),
+			_ => Command::new(&tool[0]).args(&tool[1..]).arg("true").status(),
+		};
+
+		match status {
+			Ok(s) if s.success() => {
+				if keeps_credentials(tool) == Some(false) {
+					crate::warn!("`{}` doesn't keep credentials, it'll ask for every command run as root", tool[0]);
+				}
+				Ok(())
+			},
+			Ok(s) => Err(format!("`{}` exited with {s}", tool[0])),
+			Err(e) => Err(format!("`{}`: {e}", tool[0])),
+		}
+	});
+
+	if let Err(e) = validated {
+		janetrs::jpanic!("exec-root: {e}");
+	}
+
+	run(opts, tool.iter().cloned().chain(argv).collect())
+}
+
+fn run(opts: Options, argv: Vec<String>) -> Janet {
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+
+	let mut cmd = Command::new(&argv[0]);
+	cmd.args(&argv[1..])
+		.envs(env(backend.as_deref()))
+		.envs(opts.env.iter().map(|(k, v)| (k, v)))
+		.stdin(if opts.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped());
+
+	if let Some(cwd) = &opts.cwd { cmd.current_dir(cwd); }
+
+	let mut child = cmd.spawn()
+		.unwrap_or_else(|e| janetrs::jpanic!("Failed to execute `{}`: {e}", argv[0]));
+
+	if let Some(input) = opts.stdin {
+		let mut stdin = child.stdin.take().unwrap();
+		std::thread::spawn(move || stdin.write_all(&input));
+	}
+
+	let prefix = opts.stream.then(|| prefix(backend.as_deref()));
+	let stdout = pump(child.stdout.take().unwrap(), prefix.clone(), false);
+	let stderr = pump(child.stderr.take().unwrap(), prefix, true);
+
+	let status = match opts.timeout {
+		None => child.wait(),
+		Some(t) => {
+			let deadline = Instant::now() + Duration::from_secs_f64(t);
+			loop {
+				match child.try_wait() {
+					Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+					Ok(None) => {
+						let _ = child.kill();
+						let _ = child.wait();
+						super::raise("timeout", format!("`{}` timed out after {t}s", argv.join(" ")), 
+							Janet::from(janetrs::structs! { keyword![command] => argv.join(" ").as_str() }));
+					},
+					r => break r.map(Option::unwrap),
+				}
+			}
+		},
+	}.unwrap_or_else(|e| janetrs::jpanic!("`{}`: {e}", argv[0]));
+
+	Janet::from(janetrs::structs! {
+		keyword![status] => status.code().unwrap_or(-1),
+		keyword![stdout] => JanetString::new(stdout.join().unwrap()),
+		keyword![stderr] => JanetString::new(stderr.join().unwrap()),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	// a bad `:timeout` is a Janet error, not a panic inside the cfunction
+	#[test]
+	fn timeout() {
+		let rt = crate::prelude::test_runtime();
+		["-1", "(/ 0 0)", "math/inf"].iter().for_each(|t| {
+			let err = rt.run(format!("(try (exec {{:timeout {t}}} \"true\") ([e] e))")).unwrap().to_string();
+			assert!(err.contains("expected seconds"), "{t}: {err}");
+		});
+		assert_eq!(rt.run("((exec {:timeout 0} \"true\") :status)").unwrap().to_string(), "0");
+	}
+}
@@ -0,0 +1,195 @@
+use janetrs::{Janet, JanetArray, JanetStruct, JanetTuple};
+
+fn toml_val(val: toml::Value, strings: bool) -> Janet {
+	match val {
+		toml::Value::String(s)   => Janet::from(s.as_str()),
+		// plain numbers unless they'd lose precision, like json
+		toml::Value::Integer(i) if i.unsigned_abs() <= 1 << 53 => Janet::number(i as f64),
+		toml::Value::Integer(i)  => Janet::int64(i),
+		toml::Value::Float(f)    => Janet::number(f),
+		toml::Value::Boolean(b)  => Janet::boolean(b),
+		toml::Value::Datetime(d) => Janet::from(d.to_string().as_str()),
+		toml::Value::Array(a)    => a.into_iter().map(|v| toml_val(v, strings)).collect::<JanetArray>().into(),
+		toml::Value::Table(t)    => toml_table(t, strings),
+	}
+}
+
+fn toml_table(t: toml::Table, strings: bool) -> Janet {
+	t.into_iter()
+		.map(|(k, v)| (super::key(&k, strings), toml_val(v, strings)))
+		.collect::<JanetStruct>().into()
+}
+
+// `(toml/decode (slurp "Cargo.toml"))`
+#[janetrs::janet_fn(arity(range(1, 2)))]
+pub fn toml_decode(args: &mut [Janet]) -> Janet {
+	let strings = super::string_keys(args.get(1).copied());
+
+	args[0].to_string().parse::<toml::Table>()
+		.map_or_else(|e| janetrs::jpanic!("toml/decode: {e}"), |t| toml_table(t, strings))
+}
+
+// `"v"`, `'v'` or bare, shell style
+fn unquote(v: &str) -> String {
+	let v = v.trim();
+	match v.as_bytes() {
+		[b'"', .., b'"'] if v.len() > 1 => {
+			let mut chars = v[1..v.len() - 1].chars();
+			std::iter::from_fn(|| match chars.next()? {
+				'\\' => chars.next(),
+				c => Some(c),
+			}).collect()
+		},
+		[b'\'', .., b'\''] if v.len() > 1 => v[1..v.len() - 1].to_string(),
+		_ => v.to_string(),
+	}
+}
+
+// shell words, `a "b c" 'd'` is three and each comes unquoted
+fn words(s: &str) -> Vec<String> {
+	let mut words = Vec::new();
+	let mut word: Option<String> = None;
+	let mut chars = s.chars();
+
+	while let Some(c) = chars.next() {
+		match c {
+			c if c.is_whitespace() => words.extend(word.take()),
+			'"' => {
+				let w = word.get_or_insert_default();
+				while let Some(c) = chars.next() {
+					match c {
+						'"'  => break,
+						'\\' => w.extend(chars.next()),
+						c    => w.push(c),
+					}
+				}
+			},
+			'\'' => word.get_or_insert_default().extend(chars.by_ref().take_while(|c| *c != '\'')),
+			'\\' => word.get_or_insert_default().extend(chars.next()),
+			c    => word.get_or_insert_default().push(c),
+		}
+	}
+
+	words.extend(word);
+	words
+}
+
+// keys in order of first appearance, with every value they were given
+type Entries = Vec<(String, Vec<Janet>)>;
+
+// repeated keys collect into a tuple, like pacman.conf's `Server`
+fn insert(entries: &mut Entries, k: &str, v: Janet) {
+	match entries.iter_mut().find(|(e, _)| e == k) {
+		Some((_, vs)) => vs.push(v),
+		None => entries.push((k.to_string(), vec![v])),
+	}
+}
+
+fn to_struct(entries: Entries, strings: bool) -> Janet {
+	entries.into_iter()
+		.map(|(k, mut v)| (super::key(&k, strings), match v.len() {
+			1 => v.pop().unwrap(),
+			_ => v.into_iter().collect::<JanetTuple>().into(),
+		}))
+		.collect::<JanetStruct>().into()
+}
+
+// `(ini/decode (slurp "/etc/pacman.conf"))`, `{"options" {"Color" true ...} "core" {"Include" ...}}`.
+// keys before any section end up at the top level, keys without a value are `true`
+#[janetrs::janet_fn(arity(range(1, 2)))]
+pub fn ini_decode(args: &mut [Janet]) -> Janet {
+	let strings = super::string_keys(args.get(1).copied());
+
+	let mut root = Vec::new();
+	let mut sections: Vec<(String, Entries)> = Vec::new();
+	// a section that comes up again carries on where it left off
+	let mut current = None;
+
+	args[0].to_string().lines()
+		.enumerate()
+		.map(|(i, l)| (i, l.trim()))
+		.filter(|(_, l)| !(l.is_empty() || l.starts_with('#') || l.starts_with(';')))
+		.for_each(|(i, l)| {
+			if let Some(name) = l.strip_prefix('[') {
+				let name = name.strip_suffix(']')
+					.unwrap_or_else(|| janetrs::jpanic!("ini/decode: line {}: unclosed section", i + 1))
+					.trim();
+				current = Some(sections.iter().position(|(n, _)| n == name).unwrap_or_else(|| {
+					sections.push((name.to_string(), Vec::new()));
+					sections.len() - 1
+				}));
+				return;
+			}
+
+			let entries = current.map_or(&mut root, |c| &mut sections[c].1);
+			match l.split_once(['=', ':']) {
+				Some((k, v)) => insert(entries, k.trim(), Janet::from(unquote(v).as_str())),
+				None => insert(entries, l, Janet::boolean(true)),
+			}
+		});
+
+	let sections = sections.into_iter()
+		.map(|(name, e)| (name, vec![to_struct(e, strings)]))
+		.collect::<Vec<_>>();
+
+	to_struct(root.into_iter().chain(sections).collect(), strings)
+}
+
+// `(kv/decode (slurp "/etc/os-release"))`, `KEY=VALUE` lines.
+// `export` is skipped, `deps=('a' 'b')` becomes a tuple
+#[janetrs::janet_fn(arity(range(1, 2)))]
+pub fn kv_decode(args: &mut [Janet]) -> Janet {
+	let strings = super::string_keys(args.get(1).copied());
+
+	let mut entries: Entries = Vec::new();
+	args[0].to_string().lines()
+		.map(str::trim)
+		.filter(|l| !(l.is_empty() || l.starts_with('#')))
+		.filter_map(|l| l.strip_prefix("export ").unwrap_or(l).split_once('='))
+		.for_each(|(k, v)| {
+			let v = v.trim();
+			let v = match v.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+				Some(items) => words(items).iter()
+					.map(|i| Janet::from(i.as_str()))
+					.collect::<JanetTuple>().into(),
+				None => Janet::from(unquote(v).as_str()),
+			};
+
+			// later assignments win, like in a shell
+			entries.retain(|(e, _)| e != k.trim());
+			insert(&mut entries, k.trim(), v);
+		});
+
+	to_struct(entries, strings)
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn ini() {
+		let mut rt = crate::prelude::test_runtime();
+		let run = |rt: &mut janetrs::client::JanetClient, code: &str| rt.run(code).unwrap().to_string();
+
+		let conf = r##"(ini/decode "# top\n\n[a]\nX = 1\n[b]\nY=2\n[a]\nZ\n")"##;
+		assert_eq!(run(&mut rt, &format!("(get-in {conf} [:a :X])")), "1");
+		assert_eq!(run(&mut rt, &format!("(get-in {conf} [:a :Z])")), "true");
+		assert_eq!(run(&mut rt, &format!("(get-in {conf} [:b :Y])")), "2");
+
+		let err = run(&mut rt, r##"(try (ini/decode "# c\n\n[ok]\n[bad\n") ([e] e))"##);
+		assert!(err.contains("line 4"), "{err}");
+
+		assert_eq!(run(&mut rt, r##"(type ((toml/decode "a = 9007199254740993") :a))"##), ":core/s64");
+		assert_eq!(run(&mut rt, r##"(string ((toml/decode "a = 9007199254740993") :a))"##), "9007199254740993");
+		assert_eq!(run(&mut rt, r##"(type ((toml/decode "a = 3") :a))"##), ":number");
+	}
+
+	#[test]
+	fn kv() {
+		let rt = crate::prelude::test_runtime();
+		let run = |code: &str| rt.run(code).unwrap().to_string();
+
+		let conf = r##"(kv/decode "export NAME=\"Arch Linux\"\ndeps=(\"a b\" c 'd e' f\\ g)\nNAME=again\n")"##;
+		assert_eq!(run(&format!("(get {conf} :NAME)")), "again");
+		assert_eq!(run(&format!("(string/join (get {conf} :deps) \"|\")")), "a b|c|d e|f g");
+	}
+}
@@ -0,0 +1,419 @@
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use janetrs::{Janet, JanetString, TaggedJanet};
+use crate::keyword;
+
+const USER_AGENT: &str = "Bedrock Linux pmm";
+
+#[derive(Default, Clone)]
+pub struct Options {
+	pub headers: Vec<(String, String)>,
+	pub body:    Option<Vec<u8>>,
+	pub timeout: Option<u64>,
+	pub retries: u32,
+	pub limit:   Option<usize>, // `http/get-all` connections
+	pub checksum: Option<(super::checksum::Algo, String)>, // `http/download`
+}
+
+impl From<Janet> for Options {
+	fn from(opts: Janet) -> Self {
+		super::pairs(opts).into_iter().fold(Self::default(), |mut o, (k, v)| {
+			match k.to_string().trim_start_matches(':') {
+				"headers" => o.headers = super::pairs(v).into_iter()
+					.map(|(k, v)| (k.to_string(), v.to_string()))
+					.collect(),
+				"body" => o.body = match v.unwrap() {
+					TaggedJanet::String(s) => Some(s.as_bytes().to_vec()),
+					TaggedJanet::Buffer(b) => Some(b.as_bytes().to_vec()),
+					TaggedJanet::Nil => None,
+					t => janetrs::jpanic!("`:body`: expected `string` or `buffer`, got `{}`", t.kind()),
+				},
+				"timeout" => o.timeout = match v.unwrap() {
+					TaggedJanet::Number(n) => Some(n.ceil() as u64),
+					t => janetrs::jpanic!("`:timeout`: expected `number`, got `{}`", t.kind()),
+				},
+				"retries" => o.retries = match v.unwrap() {
+					TaggedJanet::Number(n) => n as u32,
+					t => janetrs::jpanic!("`:retries`: expected `number`, got `{}`", t.kind()),
+				},
+				"limit" => o.limit = match v.unwrap() {
+					TaggedJanet::Number(n) => Some(n as usize),
+					t => janetrs::jpanic!("`:limit`: expected `number`, got `{}`", t.kind()),
+				},
+				k @ ("sha256" | "sha512" | "blake3") => 
+					o.checksum = super::checksum::Algo::parse(k).map(|a| (a, v.to_string())),
+				k => janetrs::jpanic!("unknown option `:{k}`"),
+			}; o
+		})
+	}
+}
+
+impl Options {
+	// for everything but `http/download`, which is the only one that checks a checksum
+	fn unchecked(opts: Janet, f: &str) -> Self {
+		let o = Self::from(opts);
+		if let Some((a, _)) = &o.checksum {
+			janetrs::jpanic!("`:{}`: `{f}` doesn't check checksums, `http/download` does", a.name());
+		}
+		o
+	}
+}
+
+fn method(m: &str) -> minreq::Method {
+	use minreq::Method;
+
+	match m.to_uppercase().as_str() {
+		"GET"     => Method::Get,
+		"HEAD"    => Method::Head,
+		"POST"    => Method::Post,
+		"PUT"     => Method::Put,
+		"DELETE"  => Method::Delete,
+		"CONNECT" => Method::Connect,
+		"OPTIONS" => Method::Options,
+		"TRACE"   => Method::Trace,
+		"PATCH"   => Method::Patch,
+		m => Method::Custom(m.to_string()),
+	}
+}
+
+pub struct Failure {
+	pub kind:    &'static str,
+	pub message: String,
+	pub status:  Option<i32>,
+}
+
+// couldn't reach the host at all, as opposed to it misbehaving.
+// timeouts aren't counted, a slow server shouldn't take every other backend offline
+fn unreachable(e: &minreq::Error, url: &str) -> bool {
+	use std::io::ErrorKind;
+
+	match e {
+		minreq::Error::AddressNotFound => true,
+		minreq::Error::IoError(io) => match io.kind() {
+			ErrorKind::ConnectionRefused | ErrorKind::NetworkUnreachable
+				| ErrorKind::HostUnreachable | ErrorKind::NotConnected => true,
+			ErrorKind::TimedOut | ErrorKind::WouldBlock => false,
+			// a failed lookup has no kind of its own, so see if the name resolves now
+			_ => !resolves(url),
+		},
+		_ => false,
+	}
+}
+
+fn resolves(url: &str) -> bool {
+	use std::net::ToSocketAddrs;
+
+	let (scheme, rest) = url.split_once("://").unwrap_or(("http", url));
+	let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+	let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+
+	// `host:port` or `[::1]:port` as it is, the scheme's port otherwise
+	let has_port = host.rsplit_once(':').is_some_and(|(h, p)|
+		!p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()) && (!h.contains(':') || h.ends_with(']')));
+	let addr = match has_port {
+		true  => host.to_string(),
+		false => format!("{host}:{}", if scheme == "https" { 443 } else { 80 }),
+	};
+
+	addr.to_socket_addrs().is_ok()
+}
+
+// retries connection failures, 5xx and 429 with a backoff.
+// switches to offline mode if the host can't be reached
+pub fn send(m: &str, url: &str, opts: &Options) -> Result<minreq::Response, Failure> {
+	retry(m, url, opts, minreq::Request::send, |r| r.status_code)
+}
+
+fn retry<R>(m: &str, url: &str, opts: &Options,
+	send: fn(minreq::Request) -> Result<R, minreq::Error>, status: fn(&R) -> i32) -> Result<R, Failure> {
+	if crate::cache::is_offline() {
+		return Err(Failure { kind: "network", message: String::from("offline"), status: None });
+	}
+
+	let mut attempt = 0;
+	loop {
+		let mut req = minreq::Request::new(method(m), url)
+			.with_header("User-Agent", USER_AGENT)
+			.with_headers(opts.headers.iter().cloned());
+
+		if let Some(b) = &opts.body    { req = req.with_body(b.clone()); }
+		if let Some(t) = opts.timeout { req = req.with_timeout(t); }
+
+		match send(req) {
+			Ok(r) if !(status(&r) >= 500 || status(&r) == 429) => return Ok(r),
+			Err(e) if attempt >= opts.retries && unreachable(&e, url) => {
+				crate::cache::go_offline();
+				return Err(Failure { kind: "network", message: e.to_string(), status: None });
+			},
+			Err(minreq::Error::IoError(io)) if attempt >= opts.retries 
+				&& matches!(io.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) =>
+				return Err(Failure { kind: "timeout", message: io.to_string(), status: None }),
+			Err(e) if attempt >= opts.retries => return Err(Failure { kind: "network", message: e.to_string(), status: None }),
+			Ok(r) if attempt >= opts.retries => return Ok(r),
+			_ => {},
+		}
+
+		std::thread::sleep(Duration::from_millis(250 << attempt.min(6)));
+		attempt += 1;
+	}
+}
+
+fn response(r: minreq::Response) -> Janet {
+	let headers = r.headers.iter()
+		.map(|(k, v)| (Janet::from(k.as_str()), Janet::from(v.as_str())))
+		.collect::<janetrs::JanetStruct>();
+
+	Janet::from(janetrs::structs! {
+		keyword![status]  => r.status_code,
+		keyword![headers] => headers,
+		keyword![body]    => JanetString::new(r.as_bytes()),
+	})
+}
+
+// `(http/request :post url {:headers {"Authorization" token} :body "..." :timeout 10 :retries 2})`
+#[janetrs::janet_fn(arity(range(2, 3)))]
+pub fn request(args: &mut [Janet]) -> Janet {
+	let opts = Options::unchecked(args.get(2).copied().unwrap_or_else(Janet::nil), "http/request");
+	let url = args[1].to_string();
+
+	send(args[0].to_string().trim_start_matches(':'), &url, &opts).map_or_else(
+		|f| raise(&url, f),
+		response)
+}
+
+// `max-age` from `Cache-Control`, `None` if the response shouldn't be stored at all.
+// entries are keyed on every header sent, so a `Vary` naming some of them holds already, `*` never does
+fn max_age(r: &minreq::Response) -> Option<Option<u64>> {
+	let cc = r.headers.get("cache-control").map(|c| c.to_lowercase()).unwrap_or_default();
+	let directives = cc.split(',').map(str::trim).collect::<Vec<_>>();
+	let private = directives.iter().any(|d| d.starts_with("private"));
+	let vary = r.headers.get("vary").is_some_and(|v| v.split(',').any(|v| v.trim() == "*"));
+
+	match directives.contains(&"no-store") || private || vary {
+		true  => None,
+		false if directives.contains(&"no-cache") => Some(Some(0)),
+		false => Some(directives.iter().find_map(|d| d.strip_prefix("max-age=")?.parse().ok())),
+	}
+}
+
+fn bad_status(s: i32, reason: &str) -> Failure {
+	Failure { 
+		kind:    if s == 404 { "not-found" } else { "network" },
+		message: format!("{s} {reason}"),
+		status:  Some(s),
+	}
+}
+
+// body of a successful GET, anything but a 2xx is a failure.
+// served from the cache while fresh, revalidated with `ETag`/`Last-Modified` once stale.
+// doesn't touch janet so it can run on any thread
+fn get(url: &str, mut opts: Options, backend: Option<&str>) -> Result<Vec<u8>, Failure> {
+	// credentials stay out of the cache, on disk and in what's served to requests without them
+	let credentials = opts.headers.iter().any(|(k, _)| ["authorization", "cookie"].contains(&k.to_lowercase().as_str()));
+	let cache = crate::cache::CACHE.get().filter(|_| !credentials);
+	let headers = opts.headers.clone();
+	let cached = cache.filter(|c| !c.refresh || crate::cache::is_offline()).and_then(|c| c.get(url, &headers));
+
+	if let Some(e) = &cached {
+		if e.is_fresh(cache.unwrap().ttl(backend)) {
+			return Ok(e.body.clone());
+		}
+
+		if crate::cache::is_offline() {
+			crate::cache::mark_stale(backend, e.age());
+			return Ok(e.body.clone());
+		}
+
+		e.etag.iter().for_each(|t| opts.headers.push(("If-None-Match".into(), t.clone())));
+		e.last_modified.iter().for_each(|l| opts.headers.push(("If-Modified-Since".into(), l.clone())));
+	}
+
+	let res = match send("GET", url, &opts) {
+		Ok(r) => r,
+		// we may have only just found out we're offline
+		Err(_) if crate::cache::is_offline() && cached.is_some() => {
+			let e = cached.unwrap();
+			crate::cache::mark_stale(backend, e.age());
+			return Ok(e.body);
+		},
+		Err(f) if crate::cache::is_offline() => 
+			return Err(Failure { message: format!("{}, and nothing cached", f.message), ..f }),
+		Err(f) => return Err(f),
+	};
+
+	// a 304 doesn't have to repeat the validators
+	let (etag, last_modified) = (
+		res.headers.get("etag").cloned().or_else(|| cached.as_ref().and_then(|e| e.etag.clone())),
+		res.headers.get("last-modified").cloned().or_else(|| cached.as_ref().and_then(|e| e.last_modified.clone())));
+
+	let body = match (res.status_code, cached) {
+		(304, Some(e)) => e.body,
+		(200..300, _)  => res.as_bytes().to_vec(),
+		(s, _) => return Err(bad_status(s, &res.reason_phrase)),
+	};
+
+	if let (Some(cache), Some(max_age)) = (cache, max_age(&res)) {
+		cache.put(url, &headers, &crate::cache::Entry {
+			fetched:       crate::cache::now(),
+			body:          body.clone(),
+			max_age, etag, last_modified,
+		});
+	}
+
+	Ok(body)
+}
+
+fn raise(url: &str, f: Failure) -> ! {
+	super::raise(f.kind, format!("{url}: {}", f.message), match f.status {
+		Some(s) => Janet::from(janetrs::structs! {
+			keyword![url]    => url,
+			keyword![status] => s,
+		}),
+		None => Janet::from(janetrs::structs! { keyword![url] => url }),
+	})
+}
+
+#[janetrs::janet_fn(arity(range(1, 2)))]
+pub fn get_req(args: &mut [Janet]) -> Janet {
+	let opts = Options::unchecked(args.get(1).copied().unwrap_or_else(Janet::nil), "get-req");
+	let url = args[0].to_string();
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+
+	get(&url, opts, backend.as_deref())
+		.map_or_else(|f| raise(&url, f), |b| JanetString::new(b).into())
+}
+
+// `get-req` for every url at once, at most `:limit` (8 by default) in flight.
+// bodies come back in the same order, the first failure is raised
+#[janetrs::janet_fn(arity(range(1, 2)))]
+pub fn get_all(args: &mut [Janet]) -> Janet {
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	let opts = Options::unchecked(args.get(1).copied().unwrap_or_else(Janet::nil), "http/get-all");
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+
+	let urls = match args[0].unwrap() {
+		TaggedJanet::Array(a) => a.iter().map(ToString::to_string).collect::<Vec<_>>(),
+		TaggedJanet::Tuple(t) => t.iter().map(ToString::to_string).collect::<Vec<_>>(),
+		_ => janetrs::bad_slot!(args, 0, "array|tuple"),
+	};
+
+	let next = AtomicUsize::new(0);
+	let mut res = std::thread::scope(|s| (0..opts.limit.unwrap_or(8).clamp(1, urls.len().max(1)))
+		.map(|_| s.spawn(|| std::iter::from_fn(|| {
+			let i = next.fetch_add(1, Ordering::Relaxed);
+			urls.get(i).map(|url| (i, get(url, opts.clone(), backend.as_deref())))
+		}).collect::<Vec<_>>()))
+		.collect::<Vec<_>>().into_iter()
+		.flat_map(|t| t.join().unwrap())
+		.collect::<Vec<_>>());
+
+	res.sort_unstable_by_key(|(i, _)| *i);
+
+	res.into_iter()
+		.map(|(i, r)| r.map_or_else(|f| raise(&urls[i], f), |b| Janet::from(JanetString::new(b))))
+		.collect::<janetrs::JanetArray>()
+		.into()
+}
+
+fn fmt_size(n: u64) -> String {
+	match n {
+		0..1024              => format!("{n}B"),
+		1024..1048576        => format!("{:.1}KiB", n as f64 / 1024.0),
+		1048576..1073741824  => format!("{:.1}MiB", n as f64 / 1048576.0),
+		_                    => format!("{:.1}GiB", n as f64 / 1073741824.0),
+	}
+}
+
+fn io_fail(path: &str, e: impl std::fmt::Display) -> ! {
+	let _ = std::fs::remove_file(format!("{path}.part"));
+	super::raise("other", format!("{path}: {e}"), Janet::nil())
+}
+
+// `(http/download url "/tmp/pkg.tar.gz" {:sha256 "..."})`, streamed to `<path>.part` and only moved
+// into place once complete and verified. not cached, progress goes to stderr if it's a terminal
+#[janetrs::janet_fn(arity(range(2, 3)))]
+pub fn download(args: &mut [Janet]) -> Janet {
+	let opts = Options::from(args.get(2).copied().unwrap_or_else(Janet::nil));
+	let (url, path) = (args[0].to_string(), args[1].to_string());
+	let backend = Janet::dynamic("pmm-backend").map(|b| b.to_string());
+
+	let mut res = retry("GET", &url, &opts, minreq::Request::send_lazy, |r| r.status_code)
+		.unwrap_or_else(|f| raise(&url, f));
+	if !(200..300).contains(&res.status_code) {
+		raise(&url, bad_status(res.status_code, &res.reason_phrase));
+	}
+
+	let total = res.headers.get("content-length").and_then(|l| l.parse::<u64>().ok());
+	let part = format!("{path}.part");
+
+	if let Some(dir) = std::path::Path::new(&path).parent().filter(|d| !d.as_os_str().is_empty()) {
+		std::fs::create_dir_all(dir).unwrap_or_else(|e| io_fail(&path, e));
+	}
+	let mut out = std::io::BufWriter::new(std::fs::File::create(&part).unwrap_or_else(|e| io_fail(&path, e)));
+	let mut hasher = opts.checksum.as_ref().map(|(a, _)| super::checksum::Hasher::new(*a));
+
+	let progress = std::io::IsTerminal::is_terminal(&std::io::stderr()).then(|| format!("{}{}",
+		super::exec::prefix(backend.as_deref()),
+		url.rsplit('/').next().unwrap_or(&url)));
+	let (mut buf, mut done, mut shown) = (vec![0; 64 * 1024], 0u64, Instant::now());
+
+	loop {
+		let n = res.read(&mut buf).unwrap_or_else(|e| {
+			let _ = std::fs::remove_file(&part);
+			raise(&url, Failure { kind: "network", message: e.to_string(), status: None })
+		});
+		if n == 0 { break; }
+
+		out.write_all(&buf[..n]).unwrap_or_else(|e| io_fail(&path, e));
+		if let Some(h) = hasher.as_mut() { h.update(&buf[..n]); }
+		done += n as u64;
+
+		if let Some(p) = progress.as_ref().filter(|_| shown.elapsed() > Duration::from_millis(100)) {
+			match total {
+				Some(t) => eprint!("\r\x1b[K{p} {}/{}", fmt_size(done), fmt_size(t)),
+				None    => eprint!("\r\x1b[K{p} {}", fmt_size(done)),
+			}
+			shown = Instant::now();
+		}
+	}
+
+	if progress.is_some() { eprint!("\r\x1b[K"); }
+	out.flush().unwrap_or_else(|e| io_fail(&path, e));
+
+	if let (Some(h), Some((algo, expected))) = (hasher, opts.checksum.as_ref()) {
+		let actual = h.finish();
+		if !actual.eq_ignore_ascii_case(expected.trim()) {
+			let _ = std::fs::remove_file(&part);
+			super::checksum::mismatch(*algo, &path, expected, &actual);
+		}
+	}
+
+	std::fs::rename(&part, &path).unwrap_or_else(|e| io_fail(&path, e));
+	Janet::from(path.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn resolve() {
+		assert!(super::resolves("http://localhost:8080/x?y"));
+		assert!(super::resolves("https://user@127.0.0.1/"));
+		assert!(super::resolves("http://[::1]:9/"));
+		assert!(super::resolves("localhost"));
+		assert!(!super::resolves("https://pmm-test.invalid/index"));
+	}
+
+	// refused before anything is sent, so no network needed
+	#[test]
+	fn checksum_outside_download() {
+		let rt = crate::prelude::test_runtime();
+		["(http/request :get \"http://localhost:9\" {:sha256 \"00\"})",
+		 "(get-req \"http://localhost:9\" {:blake3 \"00\"})",
+		 "(http/get-all [\"http://localhost:9\"] {:sha512 \"00\"})"].iter().for_each(|call| {
+			let err = rt.run(format!("(try {call} ([e] e))")).unwrap().to_string();
+			assert!(err.contains("doesn't check checksums"), "{call}: {err}");
+		});
+	}
+}
@@ -0,0 +1,120 @@
+use janetrs::{Janet, JanetArray, JanetStruct, TaggedJanet};
+use miniserde::json::{Number, Value};
+
+fn decode_val(val: Value, strings: bool) -> Janet {
+	match val {
+		Value::Null      => Janet::nil(),
+		Value::Bool(b)   => Janet::boolean(b),
+		// plain numbers unless they'd lose precision
+		Value::Number(Number::U64(n)) if n <= 1 << 53 => Janet::number(n as f64),
+		Value::Number(Number::I64(n)) if n.unsigned_abs() <= 1 << 53 => Janet::number(n as f64),
+		Value::Number(Number::U64(n)) => Janet::uint64(n),
+		Value::Number(Number::I64(n)) => Janet::int64(n),
+		Value::Number(Number::F64(n)) => Janet::number(n),
+		Value::String(s) => Janet::string(s.into()),
+		Value::Array(a)  => a.into_iter().map(|v| decode_val(v, strings)).collect::<JanetArray>().into(),
+		Value::Object(o) => o.into_iter()
+			.map(|(k, v)| (super::key(&k, strings), decode_val(v, strings)))
+			.collect::<JanetStruct>().into(),
+	}
+}
+
+// `(json/decode str &opt {:keys :string})`, any top level value
+#[janetrs::janet_fn(arity(range(1, 2)))]
+pub fn decode(args: &mut [Janet]) -> Janet {
+	let strings = super::string_keys(args.get(1).copied());
+
+	let val = match args[0].unwrap() {
+		TaggedJanet::String(s) => miniserde::json::from_str::<Value>(&s.to_str_lossy()),
+		TaggedJanet::Buffer(b) => miniserde::json::from_str::<Value>(&b.to_str_lossy()),
+		_ => janetrs::bad_slot!(args, 0, "string|buffer"),
+	}.unwrap_or_else(|_| janetrs::jpanic!("json/decode: invalid json"));
+
+	decode_val(val, strings)
+}
+
+fn encode_str(s: &str, out: &mut String) {
+	out.push('"');
+	s.chars().for_each(|c| match c {
+		'"'  => out.push_str("\\\""),
+		'\\' => out.push_str("\\\\"),
+		'\n' => out.push_str("\\n"),
+		'\r' => out.push_str("\\r"),
+		'\t' => out.push_str("\\t"),
+		c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+		c => out.push(c),
+	});
+	out.push('"');
+}
+
+// keywords print with their `:`
+fn name(v: Janet) -> String {
+	match v.unwrap() {
+		TaggedJanet::Keyword(_) => v.to_string().trim_start_matches(':').to_string(),
+		_ => v.to_string(),
+	}
+}
+
+fn encode_val(val: Janet, indent: Option<usize>, depth: usize, out: &mut String) {
+	let newline = |out: &mut String, depth: usize| if let Some(i) = indent {
+		out.push('\n');
+		out.push_str(&" ".repeat(i * depth));
+	};
+
+	let seq = |items: Vec<Janet>, out: &mut String| {
+		out.push('[');
+		items.iter().enumerate().for_each(|(i, v)| {
+			if i > 0 { out.push(','); }
+			newline(out, depth + 1);
+			encode_val(*v, indent, depth + 1, out);
+		});
+		if !items.is_empty() { newline(out, depth); }
+		out.push(']');
+	};
+
+	match val.unwrap() {
+		TaggedJanet::Nil        => out.push_str("null"),
+		TaggedJanet::Boolean(b) => out.push_str(if b { "true" } else { "false" }),
+		TaggedJanet::Number(n) if !n.is_finite() => janetrs::jpanic!("json/encode: can't encode `{n}`"),
+		TaggedJanet::Number(n)  => out.push_str(&n.to_string()),
+		TaggedJanet::String(s)  => encode_str(&s.to_str_lossy(), out),
+		TaggedJanet::Buffer(b)  => encode_str(&b.to_str_lossy(), out),
+		TaggedJanet::Symbol(_) | TaggedJanet::Keyword(_) => encode_str(&name(val), out),
+		TaggedJanet::Array(a)   => seq(a.iter().copied().collect(), out),
+		TaggedJanet::Tuple(t)   => seq(t.iter().copied().collect(), out),
+		TaggedJanet::Struct(_) | TaggedJanet::Table(_) => {
+			let mut pairs = super::pairs(val);
+			// deterministic output, handy for caching and diffs
+			pairs.sort_by_key(|(k, _)| name(*k));
+
+			out.push('{');
+			pairs.iter().enumerate().for_each(|(i, (k, v))| {
+				if i > 0 { out.push(','); }
+				newline(out, depth + 1);
+				encode_str(&name(*k), out);
+				out.push(':');
+				if indent.is_some() { out.push(' '); }
+				encode_val(*v, indent, depth + 1, out);
+			});
+			if !pairs.is_empty() { newline(out, depth); }
+			out.push('}');
+		},
+		// int/s64 and int/u64
+		TaggedJanet::Abstract(a) if a.get::<i64>().is_ok() || a.get::<u64>().is_ok() => out.push_str(&val.to_string()),
+		t => janetrs::jpanic!("json/encode: can't encode `{}`", t.kind()),
+	}
+}
+
+// `(json/encode val &opt {:pretty true})`
+#[janetrs::janet_fn(arity(range(1, 2)))]
+pub fn encode(args: &mut [Janet]) -> Janet {
+	let pretty = args.get(1).copied().map(super::pairs).unwrap_or_default().into_iter()
+		.fold(false, |_, (k, v)| match k.to_string().trim_start_matches(':') {
+			"pretty" => !matches!(v.unwrap(), TaggedJanet::Nil | TaggedJanet::Boolean(false)),
+			k => janetrs::jpanic!("unknown option `:{k}`"),
+		});
+
+	let mut out = String::new();
+	encode_val(args[0], pretty.then_some(2), 0, &mut out);
+	Janet::from(out.as_str())
+}
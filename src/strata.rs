@@ -0,0 +1,138 @@
+// Bedrock Linux strata, each backend can be bound to one with `:backends {"apt" {:stratum "debian"}}`,
+// or instantiated once per stratum that has its package manager with `:discover true`
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+
+// `:bedrock-root`, somewhere else for testing against a fake tree
+static ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+// stratum of each backend bound to one
+pub static STRATA: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+pub fn set_root(root: impl Into<PathBuf>) {
+	*ROOT.write().unwrap() = Some(root.into());
+}
+
+pub fn root() -> PathBuf {
+	ROOT.read().unwrap().clone().unwrap_or_else(|| PathBuf::from("/bedrock"))
+}
+
+pub fn of(backend: Option<&str>) -> Option<&'static str> {
+	STRATA.get().and_then(|s| s.get(backend?)).map(String::as_str)
+}
+
+// where the stratum's files live, as seen from outside of it
+pub fn dir(stratum: &str) -> PathBuf {
+	root().join("strata").join(stratum)
+}
+
+pub fn exists(stratum: &str) -> bool {
+	dir(stratum).is_dir()
+}
+
+pub fn is_bedrock() -> bool {
+	root().join("strata").is_dir()
+}
+
+// enabled strata, by name. aliases are symlinks and skipped, as is bedrock's own
+pub fn list() -> Vec<String> {
+	let enabled = root().join("run/enabled_strata");
+
+	let mut strata = std::fs::read_dir(root().join("strata")).into_iter().flatten()
+		.filter_map(|e| e.ok())
+		.filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+		.map(|e| e.file_name().to_string_lossy().into_owned())
+		.filter(|s| s != "bedrock" && !s.starts_with('.'))
+		.filter(|s| !enabled.is_dir() || enabled.join(s).exists())
+		.collect::<Vec<_>>();
+
+	strata.sort_unstable();
+	strata
+}
+
+// whether `bin` is installed in `stratum`, or on `PATH` without one
+pub fn provides(stratum: Option<&str>, bin: &str) -> bool {
+	match stratum {
+		// may well be an absolute symlink, meaningless from out here
+		Some(s) => ["usr/bin", "bin", "usr/sbin", "sbin", "usr/local/bin"].iter()
+			.any(|d| dir(s).join(d).join(bin).symlink_metadata().is_ok()),
+		None => std::env::var_os("PATH").is_some_and(|p| std::env::split_paths(&p)
+			.any(|d| d.join(bin).is_file())),
+	}
+}
+
+// `argv` as run inside `stratum`, through `strat` if there is one.
+// otherwise chroot into its directory, which needs root
+pub fn command(stratum: Option<&str>, argv: Vec<String>) -> Vec<String> {
+	let Some(s) = stratum else { return argv };
+	let strat = root().join("bin/strat");
+
+	match strat.is_file() {
+		true  => [strat.to_string_lossy().into_owned(), s.to_string()].into_iter().chain(argv).collect(),
+		false => [String::from("chroot"), dir(s).to_string_lossy().into_owned()].into_iter().chain(argv).collect(),
+	}
+}
+
+// tests that point `ROOT` somewhere take turns
+#[cfg(test)]
+pub static TEST_ROOT: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+// a fake Bedrock tree, `strata/<name>/usr/bin/<pm>`
+#[cfg(test)]
+pub fn fake_tree(name: &str, strata: &[(&str, &[&str])]) -> PathBuf {
+	let root = crate::util::test_dir(name);
+	strata.iter().for_each(|(s, bins)| {
+		let bin = root.join("strata").join(s).join("usr/bin");
+		std::fs::create_dir_all(&bin).unwrap();
+		bins.iter().for_each(|b| std::fs::write(bin.join(b), "").unwrap());
+	});
+	root
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn strata() {
+		let _lock = TEST_ROOT.lock().unwrap_or_else(|e| e.into_inner());
+		let root = fake_tree("strata", &[
+			("debian", &["apt-get", "dpkg"]), ("void", &[]), ("gentoo", &["emerge"]), ("bedrock", &["brl"]),
+		]);
+		// an alias, and a package manager that's an absolute symlink into the stratum
+		std::os::unix::fs::symlink("debian", root.join("strata/deb")).unwrap();
+		std::fs::create_dir_all(root.join("strata/arch/usr/bin")).unwrap();
+		std::os::unix::fs::symlink("/usr/bin/pacman-nowhere", root.join("strata/arch/usr/bin/pacman")).unwrap();
+		set_root(&root);
+
+		assert!(is_bedrock());
+		assert_eq!(list(), ["arch", "debian", "gentoo", "void"]);
+
+		// only the enabled ones once bedrock says which those are
+		["arch", "debian", "void"].iter()
+			.for_each(|s| std::fs::create_dir_all(root.join("run/enabled_strata").join(s)).unwrap());
+		assert_eq!(list(), ["arch", "debian", "void"]);
+
+		assert!(provides(Some("debian"), "dpkg"));
+		assert!(provides(Some("arch"), "pacman"));
+		assert!(!provides(Some("void"), "apt-get"));
+		assert!(!provides(Some("nowhere"), "apt-get"));
+		assert!(exists("void") && !exists("nowhere"));
+
+		assert_eq!(command(Some("void"), vec![String::from("xbps-query")]),
+			["chroot", &root.join("strata/void").to_string_lossy(), "xbps-query"]);
+		std::fs::create_dir_all(root.join("bin")).unwrap();
+		std::fs::write(root.join("bin/strat"), "").unwrap();
+		assert_eq!(command(Some("void"), vec![String::from("xbps-query")]),
+			[&root.join("bin/strat").to_string_lossy(), "void", "xbps-query"]);
+		assert_eq!(command(None, vec![String::from("ls")]), ["ls"]);
+
+		set_root(root.join("nowhere"));
+		assert!(!is_bedrock());
+		assert!(list().is_empty());
+		assert!(provides(None, "sh"));
+		assert!(!provides(None, "pmm-test-not-installed"));
+	}
+}
@@ -0,0 +1,108 @@
+// other package managers' command lines on top of pmm's own actions, so `:ui "pacman"` in the config
+// (or `--ui=pacman`) makes `pmm -Ss foo` search and `pmm -S foo` add, like Bedrock's pmm does
+
+use crate::{err, Action};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Flavour {
+	#[default]
+	Pmm,
+	Pacman,
+	Apt,
+	Xbps,
+}
+
+impl Flavour {
+	pub fn parse(s: &str) -> Option<Self> {
+		match s {
+			"pmm"    => Some(Self::Pmm),
+			"pacman" => Some(Self::Pacman),
+			"apt"    => Some(Self::Apt),
+			"xbps"   => Some(Self::Xbps),
+			_ => None,
+		}
+	}
+
+	// `Pmm` is handled by the caller, it has a grammar of its own.
+	// `short` are the short flags as given, `Syu` for `-Syu`
+	pub fn action<'d>(self, short: &[String], verbs: &'d [String]) -> Action<'d> {
+		match self {
+			Self::Pmm    => unreachable!(),
+			Self::Pacman => pacman(short, verbs),
+			Self::Apt    => apt(short, verbs),
+			Self::Xbps   => xbps(short, verbs),
+		}
+	}
+}
+
+fn one<'d>(cmd: &str, verbs: &'d [String]) -> &'d str {
+	match verbs {
+		[v] => v,
+		[]  => err!("`{cmd}` expected an argument"),
+		_   => err!("`{cmd}` expected only one argument"),
+	}
+}
+
+fn some<'d>(cmd: &str, verbs: &'d [String]) -> &'d [String] {
+	match verbs.is_empty() {
+		true  => err!("`{cmd}` expected at least one argument"),
+		false => verbs,
+	}
+}
+
+fn upgrade(cmd: &str) -> ! {
+	err!("`{cmd}`: pmm can't upgrade packages yet")
+}
+
+// the operation is the capital letter, its options may come with it (`-Ss`) or on their own (`-S -s`)
+fn pacman<'d>(short: &[String], verbs: &'d [String]) -> Action<'d> {
+	let Some(op) = short.iter().find(|f| f.starts_with(|c: char| c.is_ascii_uppercase())) else {
+		err!("No operation specified, expected `-S` or `-R`")
+	};
+
+	let has = |c: char| short.iter().any(|f| f.contains(c));
+	let cmd = format!("-{op}");
+
+	match &op[..1] {
+		"S" if has('u') => upgrade(&cmd),
+		"S" if has('s') => Action::Search(verbs),
+		"S" if has('i') => Action::Info(one(&cmd, verbs)),
+		"S" if has('c') => Action::CacheClean,
+		"S" => Action::Add(one(&cmd, verbs)),
+		"R" => Action::Del(some(&cmd, verbs)),
+		_ => err!("Unsupported operation `{cmd}`"),
+	}
+}
+
+fn apt<'d>(short: &[String], verbs: &'d [String]) -> Action<'d> {
+	let Some((cmd, rest)) = verbs.split_first() else { err!("No verbs provided") };
+	if let Some(f) = short.first() {
+		err!("Unknown flag `-{f}` for `{cmd}`");
+	}
+
+	match cmd.as_str() {
+		"search" => Action::Search(rest),
+		"show"   => Action::Info(one(cmd, rest)),
+		"install" => Action::Add(one(cmd, rest)),
+		"remove" | "purge" => Action::Del(some(cmd, rest)),
+		"clean" | "autoclean" => Action::CacheClean,
+		"update" | "upgrade" | "full-upgrade" | "dist-upgrade" => upgrade(cmd),
+		a => err!("Unknown action `{a}`"),
+	}
+}
+
+// one command per tool, `pmm xbps-query -Rs foo`
+fn xbps<'d>(short: &[String], verbs: &'d [String]) -> Action<'d> {
+	let Some((cmd, rest)) = verbs.split_first() else { err!("No verbs provided") };
+	let has = |c: char| short.iter().any(|f| f.contains(c));
+
+	match cmd.as_str() {
+		"xbps-install" if has('u') => upgrade(cmd),
+		"xbps-install" => Action::Add(one(cmd, rest)),
+		"xbps-query" if has('s') => Action::Search(rest),
+		"xbps-query" => Action::Info(one(cmd, rest)),
+		"xbps-remove" if has('O') => Action::CacheClean,
+		"xbps-remove" => Action::Del(some(cmd, rest)),
+		a => err!("Unknown action `{a}`"),
+	}
+}
@@ -16,6 +16,25 @@ pub fn prompt_blocking(p: impl std::fmt::Display) -> String {
 	std::io::stdin().lines().next().unwrap().unwrap() // should never fail ??
 }
 
+// how many edits from `a` to `b`
+fn distance(a: &str, b: &str) -> usize {
+	let b = b.chars().collect::<Vec<_>>();
+
+	a.chars().fold((0..=b.len()).collect::<Vec<_>>(), |prev, ca| {
+		let mut row = vec![prev[0] + 1];
+		b.iter().enumerate().for_each(|(j, cb)| {
+			row.push((prev[j] + (ca != *cb) as usize).min(prev[j + 1] + 1).min(row[j] + 1));
+		}); row
+	})[b.len()]
+}
+
+// what `name` was probably meant to be, for suggestions
+pub fn closest<'a>(name: &str, among: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+	among.map(|c| (distance(name, c), c))
+		.filter(|(d, c)| *d <= c.len() / 3 + 1)
+		.min()
+		.map(|(_, c)| c)
+}
 
 pub trait JanetInto<T> {
 	fn janet_into(self) -> T;
@@ -85,3 +104,12 @@ impl JanetInto<(u8, u8, u8)> for Janet {
 // 		self.try_into().unwrap_or_else(|e| crate::err!("{e}"))
 // 	}
 // }
+
+// an empty directory of its own for each test
+#[cfg(test)]
+pub fn test_dir(name: &str) -> std::path::PathBuf {
+	let dir = std::env::temp_dir().join(format!("pmm-test-{}-{name}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
@@ -59,7 +59,7 @@ impl World {
 	pub fn new(path: impl AsRef<std::path::Path>) -> Self {
 		let file = File::options()
 			.read(true).write(true)
-			.create(true).open(&path)
+			.create(true).truncate(false).open(&path)
 			.unwrap_or_else(|e| crate::err!("{}: {e}", path.as_ref().display()));
 		
 		let buf = std::io::BufReader::new(file.try_clone()
@@ -91,16 +91,36 @@ impl World {
 		self.save().unwrap_or_else(|e| crate::err!("{e}"));
 	}
 
+	// `(package, backend)` pairs, for completion. unlike `new` this doesn't create the file
+	pub fn read(path: impl AsRef<std::path::Path>) -> Vec<(String, String)> {
+		File::open(path).ok()
+			.and_then(|f| Self::deserialize(std::io::BufReader::new(f)).ok())
+			.map(|d| d.into_iter().map(|(k, v)| (k, v.backend)).collect())
+			.unwrap_or_default()
+	}
+
+	pub fn remove_package(&mut self, name: &str) {
+		if self.data.remove(name).is_some() {
+			self.save().unwrap_or_else(|e| crate::err!("{e}"));
+		}
+	}
+
+	pub fn get(&self, name: &str) -> Option<&PackageEntry> {
+		self.data.get(name)
+	}
+
 	fn save(&mut self) -> std::io::Result<()> {
-		use std::io::Write;
+		use std::io::{Seek, Write};
+		// from the top, or the write lands where the last one ended
 		self.file.set_len(0)?;
+		self.file.rewind()?;
 		self.file.write_all(self.serialize().as_bytes())?;
 		Ok(())
 	}
 
 	fn deserialize(b: impl BufRead) -> Result<BTreeMap<String, PackageEntry>, String> {
 		b.lines()
-			.filter_map(|l| l.ok())
+			.map_while(Result::ok)
 			.enumerate()
 			.filter(|(_, l)| !l.chars().all(char::is_whitespace) || !l.starts_with('#'))
 			.map(|(i, l)| {
@@ -137,7 +157,7 @@ impl World {
 	fn serialize(&self) -> String {
 		self.data.iter().map(|(k, v)|
 			format!("{} {} {}{}\n", 
-				v.backend, k, v.version.to_string(),
+				v.backend, k, v.version,
 				v.alias.as_ref().map_or(String::new(), |a| format!(" {a}"))))
 			.collect()
 	}